@@ -1,12 +1,941 @@
 //! Trait(s) for patchable structs in Rust.
 //! You probably want [`patchable`](https://docs.rs/patchable) instead, for derive functionality.
 
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::num::{Saturating, Wrapping};
+use std::ops::AddAssign;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::sync::atomic::{self, Ordering};
+use std::time::SystemTime;
+
 /// Trait to indicate that a type can be patched.
+///
+/// There is no impl for `&mut T`, as it would overlap with the blanket impls such as `Patchable<Option<T>> for T`.
+/// It is not needed either: `apply_patch` takes `&mut self`, so it can be called on a `&mut T` directly,
+/// and generic code can take `&mut T` where `T: Patchable<P>`.
+///
+/// ```
+/// use patchable_core::Patchable;
+///
+/// fn reset<T: Patchable<Option<T>> + Default>(value: &mut T) {
+///     value.apply_patch(Some(T::default()));
+/// }
+///
+/// let mut value = String::from("a");
+/// let borrowed = &mut value;
+/// borrowed.apply_patch(Some(String::from("b")));
+/// reset(borrowed);
+/// assert_eq!(value, "");
+/// ```
 pub trait Patchable<P> {
     fn apply_patch(&mut self, patch: P);
+
+    /// Applies a borrowed patch, so the same patch can be applied to many targets.
+    ///
+    /// ```
+    /// use patchable_core::Patchable;
+    ///
+    /// let patch = Some(String::from("new"));
+    /// let mut a = String::from("a");
+    /// let mut b = String::from("b");
+    /// a.apply_patch_ref(&patch);
+    /// b.apply_patch_ref(&patch);
+    /// assert_eq!(a, "new");
+    /// assert_eq!(b, "new");
+    /// ```
+    fn apply_patch_ref(&mut self, patch: &P) where P: Clone {
+        self.apply_patch(patch.clone());
+    }
+
+    /// Applies `(priority, patch)` pairs from lowest to highest priority, so the highest priority wins.
+    /// Patches with equal priority are applied in the order they were given.
+    ///
+    /// ```
+    /// use patchable_core::Patchable;
+    ///
+    /// let mut value = 0;
+    /// value.apply_patches_ordered(vec![(2, Some(20)), (1, Some(10)), (2, Some(21))]);
+    /// assert_eq!(value, 21);
+    /// ```
+    fn apply_patches_ordered<K, I>(&mut self, patches: I) where K: Ord, I: IntoIterator<Item = (K, P)> {
+        let mut patches: Vec<(K, P)> = patches.into_iter().collect();
+        patches.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (_, patch) in patches {
+            self.apply_patch(patch);
+        }
+    }
+
+    /// Applies a patch, adding the number of values it changes to `counter`, see [`Patch::count_changes`].
+    ///
+    /// ```
+    /// use patchable_core::Patchable;
+    ///
+    /// let mut changed = 0;
+    /// let mut a = 1;
+    /// let mut b = 2;
+    /// a.apply_patch_counting(Some(10), &mut changed);
+    /// b.apply_patch_counting(None, &mut changed);
+    /// assert_eq!(changed, 1);
+    /// ```
+    fn apply_patch_counting(&mut self, patch: P, counter: &mut usize) where P: Patch {
+        *counter += patch.count_changes();
+        self.apply_patch(patch);
+    }
+}
+
+/// Trait to indicate that a type can be patched, with a patch that may be rejected.
+///
+/// When an error is returned, the value is left unchanged.
+pub trait TryPatchable<P> {
+    fn try_apply_patch(&mut self, patch: P) -> Result<(), PatchError>;
+}
+
+/// Error returned when a patch is rejected by [`TryPatchable::try_apply_patch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PatchError {
+    /// The incoming value of `field` failed a validation check.
+    Validation {
+        field: &'static str,
+        message: &'static str,
+    },
+    /// A write of `len` elements at `offset` does not fit in a target of length `target_len`.
+    OutOfRange {
+        offset: usize,
+        len: usize,
+        target_len: usize,
+    },
+    /// A patch for the `expected` variant of an enum was applied to a value of the `found` variant.
+    VariantMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// An [`AnyPatch`] holds a value of another type than the one it is applied to.
+    TypeMismatch,
+    /// `field` was set in a patch from a client of `version`, but is only accepted from `min_version` on.
+    UnsupportedField {
+        field: &'static str,
+        min_version: u32,
+        version: u32,
+    },
+    /// A [`Weak`] reference was patched after its value was dropped.
+    Dangling,
+    /// The patched value as a whole failed a check, such as one from `#[patch(validate_all = "...")]`.
+    Invariant {
+        message: &'static str,
+    },
+    /// Some but not all fields of `group` were set in a patch, `missing` are the ones that were not.
+    PartialGroup {
+        group: &'static str,
+        missing: Vec<&'static str>,
+    },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Validation { field, message } => write!(f, "invalid value for `{}`: {}", field, message),
+            PatchError::OutOfRange { offset, len, target_len } => {
+                write!(f, "cannot write {} elements at offset {} into a target of length {}", len, offset, target_len)
+            },
+            PatchError::VariantMismatch { expected, found } => {
+                write!(f, "cannot apply a patch for variant `{}` to variant `{}`", expected, found)
+            },
+            PatchError::TypeMismatch => write!(f, "the patch holds a value of another type than the target"),
+            PatchError::UnsupportedField { field, min_version, version } => {
+                write!(f, "`{}` requires version {} or later, but the patch is for version {}", field, min_version, version)
+            },
+            PatchError::Dangling => write!(f, "the patched value was dropped"),
+            PatchError::Invariant { message } => write!(f, "the patched value is invalid: {}", message),
+            PatchError::PartialGroup { group, missing } => {
+                write!(f, "group `{}` is only partly set, missing `{}`", group, missing.join("`, `"))
+            },
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Trait for patch types, implemented by the derive for generated patch structs.
+pub trait Patch {
+    /// Creates a patch that changes nothing.
+    fn empty() -> Self where Self: Sized;
+
+    /// Returns `true` if applying this patch would change nothing.
+    fn is_empty(&self) -> bool;
+
+    /// Resets this patch to change nothing, keeping any allocations it can reuse, so one patch can be refilled in a loop.
+    ///
+    /// ```
+    /// use patchable_core::Patch;
+    ///
+    /// let mut patches = vec![Some(1), Some(2)];
+    /// Patch::clear(&mut patches);
+    /// assert!(Patch::is_empty(&patches));
+    /// assert!(patches.capacity() >= 2);
+    /// ```
+    fn clear(&mut self) where Self: Sized {
+        *self = Self::empty();
+    }
+
+    /// Returns the number of values this patch changes, counting the fields of nested patches separately.
+    /// By default, this is one unless the patch is empty.
+    fn count_changes(&self) -> usize {
+        !self.is_empty() as usize
+    }
+}
+
+impl<T> Patch for Option<T> {
+    fn empty() -> Self {
+        None
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_none()
+    }
+}
+
+impl<P> Patch for Vec<P> where P: Patch {
+    fn empty() -> Self {
+        Vec::new()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.iter().all(Patch::is_empty)
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+
+    fn count_changes(&self) -> usize {
+        self.iter().map(Patch::count_changes).sum()
+    }
+}
+
+impl<P> Patch for InnerPatch<P> where P: Patch {
+    fn empty() -> Self {
+        InnerPatch(P::empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn count_changes(&self) -> usize {
+        self.0.count_changes()
+    }
+}
+
+impl<P> Patch for InitPatch<P> where P: Patch {
+    fn empty() -> Self {
+        InitPatch(P::empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn count_changes(&self) -> usize {
+        self.0.count_changes()
+    }
+}
+
+/// Patch for a list that can leave it alone, replace it, or clear it.
+///
+/// For an `Option<Vec<T>>`, a plain `Option` patch can only leave the list alone or replace it,
+/// and `Clear` sets it to `Some(vec![])`.
+/// Unlike a `Vec` of patches, which applies each patch in turn to the same target, this replaces the list as a whole.
+///
+/// ```
+/// use patchable_core::{ListPatch, Patchable};
+///
+/// let mut tags = Some(vec!["a"]);
+/// tags.apply_patch(ListPatch::Keep);
+/// assert_eq!(tags, Some(vec!["a"]));
+/// tags.apply_patch(ListPatch::Replace(vec!["b", "c"]));
+/// assert_eq!(tags, Some(vec!["b", "c"]));
+/// tags.apply_patch(ListPatch::Clear);
+/// assert_eq!(tags, Some(vec![]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListPatch<T> {
+    /// Leave the list unchanged.
+    Keep,
+    /// Replace the whole list.
+    Replace(Vec<T>),
+    /// Set the list to an empty list.
+    Clear,
+}
+
+impl<T> Patchable<ListPatch<T>> for Vec<T> {
+    fn apply_patch(&mut self, patch: ListPatch<T>) {
+        match patch {
+            ListPatch::Keep => {},
+            ListPatch::Replace(list) => *self = list,
+            ListPatch::Clear => self.clear(),
+        }
+    }
+}
+
+impl<T> Patchable<ListPatch<T>> for Option<Vec<T>> {
+    fn apply_patch(&mut self, patch: ListPatch<T>) {
+        match patch {
+            ListPatch::Keep => {},
+            ListPatch::Replace(list) => *self = Some(list),
+            ListPatch::Clear => *self = Some(Vec::new()),
+        }
+    }
+}
+
+/// Merges list patches: `Keep` leaves this patch alone, and anything else replaces it.
+impl<T> Patchable<ListPatch<T>> for ListPatch<T> {
+    fn apply_patch(&mut self, patch: ListPatch<T>) {
+        if !matches!(patch, ListPatch::Keep) {
+            *self = patch;
+        }
+    }
+}
+
+impl<T> Patch for ListPatch<T> {
+    fn empty() -> Self {
+        ListPatch::Keep
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, ListPatch::Keep)
+    }
+}
+
+/// Applies an arbitrary closure as a patch, for one-off edits that do not fit a declarative patch.
+///
+/// The closure may borrow from its environment for the lifetime `'a`. It is not `Send`;
+/// use `Box<dyn FnOnce(&mut T) + Send>` for patches that move between threads.
+///
+/// ```
+/// use patchable_core::Patchable;
+///
+/// let mut value = vec![1, 2];
+/// let patch: Box<dyn FnOnce(&mut Vec<i32>)> = Box::new(|value| value.push(3));
+/// value.apply_patch(patch);
+/// assert_eq!(value, [1, 2, 3]);
+/// ```
+#[cfg(feature = "closure")]
+impl<'a, T> Patchable<Box<dyn FnOnce(&mut T) + 'a>> for T {
+    fn apply_patch(&mut self, patch: Box<dyn FnOnce(&mut T) + 'a>) {
+        patch(self);
+    }
+}
+
+/// Applies an arbitrary `Send` closure as a patch.
+#[cfg(feature = "closure")]
+impl<'a, T> Patchable<Box<dyn FnOnce(&mut T) + Send + 'a>> for T {
+    fn apply_patch(&mut self, patch: Box<dyn FnOnce(&mut T) + Send + 'a>) {
+        patch(self);
+    }
+}
+
+/// Patch that overwrites the bytes starting at `offset` of a byte buffer, leaving the rest unchanged.
+///
+/// The length of the buffer never changes. `apply_patch` truncates writes that run past the end of the buffer,
+/// while `try_apply_patch` rejects them with [`PatchError::OutOfRange`] and leaves the buffer unchanged.
+///
+/// ```
+/// use patchable_core::{PatchError, Patchable, RangePatch, TryPatchable};
+///
+/// let mut buffer = [0u8; 4];
+/// buffer.apply_patch(RangePatch { offset: 1, bytes: vec![1, 2] });
+/// assert_eq!(buffer, [0, 1, 2, 0]);
+///
+/// buffer.apply_patch(RangePatch { offset: 3, bytes: vec![3, 4] });
+/// assert_eq!(buffer, [0, 1, 2, 3]);
+///
+/// let result = buffer.try_apply_patch(RangePatch { offset: 3, bytes: vec![5, 6] });
+/// assert_eq!(result, Err(PatchError::OutOfRange { offset: 3, len: 2, target_len: 4 }));
+/// assert_eq!(buffer, [0, 1, 2, 3]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RangePatch {
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl RangePatch {
+    fn write_truncated(&self, target: &mut [u8]) {
+        let start = self.offset.min(target.len());
+        let len = self.bytes.len().min(target.len() - start);
+        target[start..start + len].copy_from_slice(&self.bytes[..len]);
+    }
+
+    fn check_range(&self, target_len: usize) -> Result<(), PatchError> {
+        match self.offset.checked_add(self.bytes.len()) {
+            Some(end) if end <= target_len => Ok(()),
+            _ => Err(PatchError::OutOfRange { offset: self.offset, len: self.bytes.len(), target_len }),
+        }
+    }
+}
+
+impl<const N: usize> Patchable<RangePatch> for [u8; N] {
+    fn apply_patch(&mut self, patch: RangePatch) {
+        patch.write_truncated(self);
+    }
+}
+
+impl<const N: usize> TryPatchable<RangePatch> for [u8; N] {
+    fn try_apply_patch(&mut self, patch: RangePatch) -> Result<(), PatchError> {
+        patch.check_range(N)?;
+        patch.write_truncated(self);
+        Ok(())
+    }
+}
+
+impl Patchable<RangePatch> for Vec<u8> {
+    fn apply_patch(&mut self, patch: RangePatch) {
+        patch.write_truncated(self);
+    }
+}
+
+impl TryPatchable<RangePatch> for Vec<u8> {
+    fn try_apply_patch(&mut self, patch: RangePatch) -> Result<(), PatchError> {
+        patch.check_range(self.len())?;
+        patch.write_truncated(self);
+        Ok(())
+    }
+}
+
+impl Patch for RangePatch {
+    fn empty() -> Self {
+        RangePatch::default()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+/// Patch that replaces the `remove_len` elements starting at `start` of a [`Vec`] with `insert`, which may have a
+/// different length, as with [`Vec::splice`].
+///
+/// `apply_patch` clamps the removed range to the end of the vector, so a `start` past the end appends `insert`,
+/// while `try_apply_patch` rejects such a range with [`PatchError::OutOfRange`] and leaves the vector unchanged.
+/// A field opts in with `#[patch(SplicePatch<T>)]`.
+///
+/// ```
+/// use patchable_core::{PatchError, Patchable, SplicePatch, TryPatchable};
+///
+/// let mut list = vec![1, 2, 3, 4];
+/// list.apply_patch(SplicePatch { start: 1, remove_len: 2, insert: vec![5, 6, 7] });
+/// assert_eq!(list, [1, 5, 6, 7, 4]);
+///
+/// list.apply_patch(SplicePatch { start: 4, remove_len: 3, insert: vec![8] });
+/// assert_eq!(list, [1, 5, 6, 7, 8]);
+///
+/// let result = list.try_apply_patch(SplicePatch { start: 4, remove_len: 2, insert: vec![] });
+/// assert_eq!(result, Err(PatchError::OutOfRange { offset: 4, len: 2, target_len: 5 }));
+/// assert_eq!(list, [1, 5, 6, 7, 8]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplicePatch<T> {
+    pub start: usize,
+    pub remove_len: usize,
+    pub insert: Vec<T>,
+}
+
+impl<T> Patchable<SplicePatch<T>> for Vec<T> {
+    fn apply_patch(&mut self, patch: SplicePatch<T>) {
+        let start = patch.start.min(self.len());
+        let end = start.saturating_add(patch.remove_len).min(self.len());
+        self.splice(start..end, patch.insert);
+    }
+}
+
+impl<T> TryPatchable<SplicePatch<T>> for Vec<T> {
+    fn try_apply_patch(&mut self, patch: SplicePatch<T>) -> Result<(), PatchError> {
+        match patch.start.checked_add(patch.remove_len) {
+            Some(end) if end <= self.len() => {
+                self.splice(patch.start..end, patch.insert);
+                Ok(())
+            },
+            _ => Err(PatchError::OutOfRange { offset: patch.start, len: patch.remove_len, target_len: self.len() }),
+        }
+    }
+}
+
+impl<T> Patch for SplicePatch<T> {
+    fn empty() -> Self {
+        SplicePatch { start: 0, remove_len: 0, insert: Vec::new() }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.remove_len == 0 && self.insert.is_empty()
+    }
+}
+
+/// Patch that edits a [`PathBuf`] in place.
+///
+/// ```
+/// use patchable_core::{PathPatch, Patchable};
+/// use std::path::PathBuf;
+///
+/// let mut path = PathBuf::from("/etc");
+/// path.apply_patch(PathPatch::Push(PathBuf::from("app/config")));
+/// path.apply_patch(PathPatch::SetExtension(String::from("toml")));
+/// assert_eq!(path, PathBuf::from("/etc/app/config.toml"));
+///
+/// let mut root = PathBuf::from("/");
+/// root.apply_patch(PathPatch::SetExtension(String::from("toml")));
+/// assert_eq!(root, PathBuf::from("/"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathPatch {
+    /// Replace the whole path.
+    Replace(PathBuf),
+    /// Join a path onto the end, with [`PathBuf::push`]. An absolute path replaces the current one.
+    Push(PathBuf),
+    /// Set the extension of the file name, with [`PathBuf::set_extension`].
+    /// Paths without a file name, such as `/` or `..`, are left unchanged.
+    SetExtension(String),
+}
+
+impl Patchable<PathPatch> for PathBuf {
+    fn apply_patch(&mut self, patch: PathPatch) {
+        match patch {
+            PathPatch::Replace(path) => *self = path,
+            PathPatch::Push(path) => self.push(path),
+            PathPatch::SetExtension(extension) => {
+                self.set_extension(extension);
+            },
+        }
+    }
+}
+
+/// Patch for a [`SystemTime`], which can also set it to the current time.
+///
+/// `Now` reads the clock when the patch is applied, not when it is created.
+///
+/// ```
+/// use patchable_core::{Patchable, TimePatch};
+/// use std::time::{Duration, SystemTime};
+///
+/// let mut updated_at = SystemTime::UNIX_EPOCH;
+/// updated_at.apply_patch(TimePatch::Keep);
+/// assert_eq!(updated_at, SystemTime::UNIX_EPOCH);
+///
+/// let patch = TimePatch::Now;
+/// let before = SystemTime::now();
+/// updated_at.apply_patch(patch);
+/// assert!(updated_at >= before);
+///
+/// updated_at.apply_patch(TimePatch::Set(SystemTime::UNIX_EPOCH + Duration::from_secs(1)));
+/// assert_eq!(updated_at, SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimePatch {
+    /// Leave the time alone.
+    Keep,
+    /// Set the time to the given value.
+    Set(SystemTime),
+    /// Set the time to [`SystemTime::now`] at the moment the patch is applied.
+    Now,
+}
+
+impl Patchable<TimePatch> for SystemTime {
+    fn apply_patch(&mut self, patch: TimePatch) {
+        match patch {
+            TimePatch::Keep => {},
+            TimePatch::Set(time) => *self = time,
+            TimePatch::Now => *self = SystemTime::now(),
+        }
+    }
+}
+
+impl Patch for TimePatch {
+    fn empty() -> Self {
+        TimePatch::Keep
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, TimePatch::Keep)
+    }
+}
+
+/// Patch that replaces a `Box<dyn Any>` with a value of the same type.
+///
+/// `apply_patch` ignores a patch holding another type, while `try_apply_patch` rejects it with
+/// [`PatchError::TypeMismatch`].
+///
+/// ```
+/// use patchable_core::{AnyPatch, PatchError, Patchable, TryPatchable};
+/// use std::any::Any;
+///
+/// let mut value: Box<dyn Any> = Box::new(1u32);
+/// value.apply_patch(AnyPatch(Box::new(2u32)));
+/// value.apply_patch(AnyPatch(Box::new("three")));
+/// assert_eq!(value.downcast_ref::<u32>(), Some(&2));
+///
+/// let result = value.try_apply_patch(AnyPatch(Box::new("three")));
+/// assert_eq!(result, Err(PatchError::TypeMismatch));
+/// ```
+pub struct AnyPatch(pub Box<dyn Any>);
+
+impl Patchable<AnyPatch> for Box<dyn Any> {
+    fn apply_patch(&mut self, patch: AnyPatch) {
+        let _ = self.try_apply_patch(patch);
+    }
+}
+
+impl TryPatchable<AnyPatch> for Box<dyn Any> {
+    fn try_apply_patch(&mut self, patch: AnyPatch) -> Result<(), PatchError> {
+        if (*patch.0).type_id() != (**self).type_id() {
+            return Err(PatchError::TypeMismatch);
+        }
+        *self = patch.0;
+        Ok(())
+    }
+}
+
+/// Patch that adds to a value instead of replacing it.
+///
+/// Addition uses [`AddAssign`], so it overflows like `+=` does: plain integers panic on overflow in debug builds,
+/// and [`Wrapping`](std::num::Wrapping) values wrap around.
+///
+/// ```
+/// use patchable_core::{Delta, Patchable};
+/// use std::num::Wrapping;
+///
+/// let mut total = 1;
+/// total.apply_patch(Delta(2));
+/// assert_eq!(total, 3);
+///
+/// let mut counter = Wrapping(250u8);
+/// counter.apply_patch(Delta(Wrapping(5)));
+/// counter.apply_patch(Delta(Wrapping(5)));
+/// assert_eq!(counter, Wrapping(4));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Delta<T>(pub T);
+
+impl<T> Patchable<Delta<T>> for T where T: AddAssign {
+    fn apply_patch(&mut self, patch: Delta<T>) {
+        *self += patch.0;
+    }
+}
+
+impl<T> Patch for Delta<T> where T: Default + PartialEq {
+    fn empty() -> Self {
+        Delta(T::default())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0 == T::default()
+    }
+}
+
+/// Trait for patching atomics through a shared reference, so that threads can patch the same value.
+///
+/// An `Option` patch stores the new value with [`swap`](std::sync::atomic::AtomicUsize::swap), and a [`Delta`]
+/// patch adds to the value with [`fetch_add`](std::sync::atomic::AtomicUsize::fetch_add), which wraps around on
+/// overflow. Both accept any [`Ordering`]; [`apply_patch_shared`](AtomicPatchable::apply_patch_shared) uses
+/// [`Ordering::SeqCst`]. `Patchable`, which has exclusive access, writes the value directly and needs no ordering,
+/// so the derive has no option for it.
+///
+/// The order between an `Option` and a `Delta` patch of the same atomic is fixed: they apply in the order they are
+/// given, so a delta after a replacement adds to the new value, and a replacement after a delta discards it.
+/// There is no option to reorder them.
+///
+/// ```
+/// use patchable_core::{AtomicPatchable, Delta, Patchable};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::thread;
+///
+/// let counter = AtomicUsize::new(0);
+/// thread::scope(|scope| {
+///     for _ in 0..4 {
+///         scope.spawn(|| {
+///             for _ in 0..100 {
+///                 counter.apply_patch_shared(Delta(1));
+///             }
+///         });
+///     }
+/// });
+/// assert_eq!(counter.load(Ordering::SeqCst), 400);
+///
+/// counter.apply_patch_ordered(Some(7), Ordering::Relaxed);
+/// let mut counter = counter;
+/// counter.apply_patch(Delta(3));
+/// assert_eq!(counter.into_inner(), 10);
+///
+/// let counter = AtomicUsize::new(1);
+/// counter.apply_patch_shared(Some(5));
+/// counter.apply_patch_shared(Delta(2));
+/// assert_eq!(counter.load(Ordering::SeqCst), 7);
+/// counter.apply_patch_shared(Delta(2));
+/// counter.apply_patch_shared(Some(5));
+/// assert_eq!(counter.load(Ordering::SeqCst), 5);
+/// ```
+pub trait AtomicPatchable<P> {
+    /// Applies a patch with the given memory ordering.
+    fn apply_patch_ordered(&self, patch: P, ordering: Ordering);
+
+    /// Applies a patch with [`Ordering::SeqCst`].
+    fn apply_patch_shared(&self, patch: P) {
+        self.apply_patch_ordered(patch, Ordering::SeqCst);
+    }
+}
+
+macro_rules! atomic_impl {
+    ($($atomic:ident($value:ty)),* $(,)?) => {$(
+        impl AtomicPatchable<Option<$value>> for atomic::$atomic {
+            #[inline]
+            fn apply_patch_ordered(&self, patch: Option<$value>, ordering: Ordering) {
+                if let Some(value) = patch {
+                    self.swap(value, ordering);
+                }
+            }
+        }
+
+        impl Patchable<Option<$value>> for atomic::$atomic {
+            #[inline]
+            fn apply_patch(&mut self, patch: Option<$value>) {
+                if let Some(value) = patch {
+                    *self.get_mut() = value;
+                }
+            }
+        }
+    )*};
+}
+
+macro_rules! atomic_delta_impl {
+    ($($atomic:ident($value:ty)),* $(,)?) => {$(
+        atomic_impl!($atomic($value));
+
+        impl AtomicPatchable<Delta<$value>> for atomic::$atomic {
+            #[inline]
+            fn apply_patch_ordered(&self, patch: Delta<$value>, ordering: Ordering) {
+                self.fetch_add(patch.0, ordering);
+            }
+        }
+
+        impl Patchable<Delta<$value>> for atomic::$atomic {
+            #[inline]
+            fn apply_patch(&mut self, patch: Delta<$value>) {
+                let value = self.get_mut();
+                *value = value.wrapping_add(patch.0);
+            }
+        }
+    )*};
+}
+
+atomic_impl!(AtomicBool(bool));
+atomic_delta_impl!(
+    AtomicI8(i8), AtomicI16(i16), AtomicI32(i32), AtomicI64(i64), AtomicIsize(isize),
+    AtomicU8(u8), AtomicU16(u16), AtomicU32(u32), AtomicU64(u64), AtomicUsize(usize),
+);
+
+/// Trait for types with a field that identifies them, implemented by the derive for the field marked
+/// `#[patch(key)]`, so that patches for lists can find elements by key.
+pub trait PatchKey {
+    type Key;
+
+    /// Returns the key of this value.
+    fn patch_key(&self) -> &Self::Key;
+}
+
+/// Visitor for the fields set in a patch, see [`VisitPatch`].
+pub trait PatchVisitor {
+    /// Called with the name and value of each field that is set.
+    fn visit<T: ?Sized>(&mut self, name: &str, value: &T);
+}
+
+/// Trait for patches whose set fields can be walked with a [`PatchVisitor`], implemented by the derive.
+///
+/// Fields of nested patches are visited with dotted names, such as `address.city`.
+///
+/// ```
+/// use patchable_core::{PatchVisitor, VisitPatch};
+///
+/// struct Names(Vec<String>);
+///
+/// impl PatchVisitor for Names {
+///     fn visit<T: ?Sized>(&mut self, name: &str, _value: &T) {
+///         self.0.push(name.to_string());
+///     }
+/// }
+///
+/// let mut names = Names(Vec::new());
+/// Some(1).visit_set(&mut names);
+/// None::<i32>.visit_set(&mut names);
+/// assert_eq!(names.0, [""]);
+/// ```
+pub trait VisitPatch {
+    /// Visits every set field, naming them relative to `name`, the name of this patch.
+    fn visit_set_as<V: PatchVisitor>(&self, name: &str, visitor: &mut V);
+
+    /// Visits every set field.
+    fn visit_set<V: PatchVisitor>(&self, visitor: &mut V) {
+        self.visit_set_as("", visitor);
+    }
+}
+
+impl<T> VisitPatch for Option<T> {
+    fn visit_set_as<V: PatchVisitor>(&self, name: &str, visitor: &mut V) {
+        if let Some(value) = self {
+            visitor.visit(name, value);
+        }
+    }
+}
+
+impl<P> VisitPatch for Vec<P> where P: VisitPatch {
+    fn visit_set_as<V: PatchVisitor>(&self, name: &str, visitor: &mut V) {
+        for patch in self {
+            patch.visit_set_as(name, visitor);
+        }
+    }
+}
+
+impl<P> VisitPatch for InnerPatch<P> where P: VisitPatch {
+    fn visit_set_as<V: PatchVisitor>(&self, name: &str, visitor: &mut V) {
+        self.0.visit_set_as(name, visitor);
+    }
+}
+
+impl<P> VisitPatch for InitPatch<P> where P: VisitPatch {
+    fn visit_set_as<V: PatchVisitor>(&self, name: &str, visitor: &mut V) {
+        self.0.visit_set_as(name, visitor);
+    }
+}
+
+impl<T> VisitPatch for ListPatch<T> {
+    fn visit_set_as<V: PatchVisitor>(&self, name: &str, visitor: &mut V) {
+        if !self.is_empty() {
+            visitor.visit(name, self);
+        }
+    }
+}
+
+impl VisitPatch for TimePatch {
+    fn visit_set_as<V: PatchVisitor>(&self, name: &str, visitor: &mut V) {
+        if !self.is_empty() {
+            visitor.visit(name, self);
+        }
+    }
+}
+
+impl<T> VisitPatch for Delta<T> where T: Default + PartialEq {
+    fn visit_set_as<V: PatchVisitor>(&self, name: &str, visitor: &mut V) {
+        if !self.is_empty() {
+            visitor.visit(name, &self.0);
+        }
+    }
+}
+
+/// Implements patching a newtype with patches of its inner value.
+///
+/// This implements `Patchable<Option<Inner>>`, which replaces the inner value, and `Patchable<Delta<Inner>>`, which
+/// wraps the delta and adds it with the wrapper's own [`AddAssign`], so wrappers with special arithmetic keep it.
+/// Patches of the wrapper itself, such as `Option<Wrapper>` and `Delta<Wrapper>`, already work through the blanket
+/// impls.
+///
+/// A tuple newtype in scope is written as `Wrapper(Inner)`, with the type parameters of a generic wrapper after the
+/// name, as in `Wrapper<T>(T)`. Otherwise, the wrapper is given as a path followed by its field and the inner type,
+/// as in `units::Meters, value: f64` or `Wrapper<T>, 0: T`, with the type parameters of a generic wrapper in front,
+/// as in `<T> Wrapper<T>, 0: T`.
+///
+/// ```
+/// use patchable_core::{impl_patchable_newtype, Delta, Patchable};
+/// use std::num::Saturating;
+/// use std::ops::AddAssign;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Meters(f64);
+///
+/// impl AddAssign for Meters {
+///     fn add_assign(&mut self, other: Meters) {
+///         self.0 += other.0;
+///     }
+/// }
+///
+/// impl_patchable_newtype!(Meters(f64));
+///
+/// let mut distance = Meters(1.0);
+/// distance.apply_patch(Some(2.0));
+/// distance.apply_patch(Delta(0.5));
+/// assert_eq!(distance, Meters(2.5));
+///
+/// let mut level = Saturating(250u8);
+/// level.apply_patch(Delta(10));
+/// assert_eq!(level, Saturating(255));
+///
+/// mod units {
+///     #[derive(Debug, PartialEq)]
+///     pub struct Seconds {
+///         pub value: u64,
+///     }
+///
+///     impl std::ops::AddAssign for Seconds {
+///         fn add_assign(&mut self, other: Seconds) {
+///             self.value += other.value;
+///         }
+///     }
+/// }
+///
+/// impl_patchable_newtype!(units::Seconds, value: u64);
+///
+/// let mut timeout = units::Seconds { value: 30 };
+/// timeout.apply_patch(Delta(15));
+/// timeout.apply_patch(Some(60));
+/// assert_eq!(timeout, units::Seconds { value: 60 });
+/// ```
+#[macro_export]
+macro_rules! impl_patchable_newtype {
+    ($wrapper:ident $(<$($param:ident),+>)? ($inner:ty)) => {
+        $crate::impl_patchable_newtype!($(<$($param),+>)? $wrapper$(<$($param),+>)?, 0: $inner);
+    };
+    ($(<$($param:ident),+>)? $wrapper:path, $field:tt: $inner:ty) => {
+        impl$(<$($param),+>)? $crate::Patchable<::core::option::Option<$inner>> for $wrapper {
+            fn apply_patch(&mut self, patch: ::core::option::Option<$inner>) {
+                if let ::core::option::Option::Some(inner) = patch {
+                    self.$field = inner;
+                }
+            }
+        }
+
+        impl$(<$($param),+>)? $crate::Patchable<$crate::Delta<$inner>> for $wrapper
+        where
+            $wrapper: ::core::ops::AddAssign,
+        {
+            fn apply_patch(&mut self, patch: $crate::Delta<$inner>) {
+                *self += Self { $field: patch.0 };
+            }
+        }
+    };
+}
+
+impl_patchable_newtype!(Saturating<T>(T));
+impl_patchable_newtype!(Wrapping<T>(T));
+
+/// Trait to name the patch type of a type, implemented by the derive.
+///
+/// This lets generic code refer to the patch of a type parameter as `T::Patch`.
+pub trait HasPatch: Patchable<<Self as HasPatch>::Patch> {
+    type Patch;
 }
 
 impl<T> Patchable<Option<T>> for T {
+    #[inline]
     fn apply_patch(&mut self, patch: Option<T>) {
         if let Some(inner) = patch {
             *self = inner;
@@ -21,3 +950,251 @@ impl<T, P> Patchable<Vec<P>> for T where T: Patchable<P> {
         }
     }
 }
+
+/// Patches the elements of a slice by position, leaving elements with a `None` patch alone.
+///
+/// A slice cannot grow, so `apply_patch` ignores patches past its end,
+/// and `try_apply_patch` rejects them with [`PatchError::OutOfRange`] without changing anything.
+///
+/// ```
+/// use patchable_core::{Delta, PatchError, Patchable, TryPatchable};
+///
+/// let mut values = vec![1, 2, 3, 4];
+/// values[1..3].apply_patch(vec![None, Some(Delta(10)), Some(Delta(20))]);
+/// assert_eq!(values, [1, 2, 13, 4]);
+///
+/// let result = values[..2].try_apply_patch(vec![Some(Delta(10)), None, Some(Delta(10))]);
+/// assert_eq!(result, Err(PatchError::OutOfRange { offset: 0, len: 3, target_len: 2 }));
+/// assert_eq!(values, [1, 2, 13, 4]);
+/// ```
+impl<T, P> Patchable<Vec<Option<P>>> for [T] where T: Patchable<P> {
+    fn apply_patch(&mut self, patches: Vec<Option<P>>) {
+        for (value, patch) in self.iter_mut().zip(patches) {
+            if let Some(patch) = patch {
+                value.apply_patch(patch);
+            }
+        }
+    }
+}
+
+impl<T, P> TryPatchable<Vec<Option<P>>> for [T] where T: Patchable<P> {
+    fn try_apply_patch(&mut self, patches: Vec<Option<P>>) -> Result<(), PatchError> {
+        if patches.len() > self.len() {
+            return Err(PatchError::OutOfRange { offset: 0, len: patches.len(), target_len: self.len() });
+        }
+        self.apply_patch(patches);
+        Ok(())
+    }
+}
+
+/// Patch that applies to the value inside an [`Option`] or smart pointer, instead of replacing the whole value.
+///
+/// Applying it to `Some` patches the contained value, and applying it to `None` does nothing.
+/// It also patches the value behind a [`Box`] or [`RefCell`], and behind an [`Rc`] or [`Arc`],
+/// which clone the value first if it is shared, like `make_mut`.
+///
+/// ```
+/// use patchable_core::{Delta, InnerPatch, Patchable};
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+/// use std::sync::Arc;
+///
+/// let mut some = Some(String::from("old"));
+/// some.apply_patch(InnerPatch(Some(String::from("new"))));
+/// assert_eq!(some.as_deref(), Some("new"));
+///
+/// let mut none: Option<String> = None;
+/// none.apply_patch(InnerPatch(Some(String::from("new"))));
+/// assert_eq!(none, None);
+///
+/// let shared = Rc::new(String::from("old"));
+/// let mut copy = Rc::clone(&shared);
+/// copy.apply_patch(InnerPatch(Some(String::from("new"))));
+/// assert_eq!((shared.as_str(), copy.as_str()), ("old", "new"));
+///
+/// let shared = Arc::new(vec![1, 2]);
+/// let mut copy = Arc::clone(&shared);
+/// copy.apply_patch(InnerPatch(Some(vec![3])));
+/// assert_eq!((shared.as_slice(), copy.as_slice()), (&[1, 2][..], &[3][..]));
+///
+/// let mut unique = Arc::new(1);
+/// unique.apply_patch(InnerPatch(Delta(2)));
+/// assert_eq!(*unique, 3);
+///
+/// let mut cell = RefCell::new(1);
+/// cell.apply_patch(InnerPatch(Delta(2)));
+/// assert_eq!(cell.into_inner(), 3);
+///
+/// let mut boxed = Box::new(1);
+/// boxed.apply_patch(InnerPatch(Delta(2)));
+/// assert_eq!(*boxed, 3);
+/// ```
+pub struct InnerPatch<P>(pub P);
+
+impl<T, P> Patchable<InnerPatch<P>> for Option<T> where T: Patchable<P> {
+    fn apply_patch(&mut self, patch: InnerPatch<P>) {
+        if let Some(inner) = self {
+            inner.apply_patch(patch.0);
+        }
+    }
+}
+
+/// Patch that applies to the value inside an [`Option`], starting from `T::default()` when it is `None`.
+///
+/// An empty patch leaves `None` as it is, so that only a patch that changes something creates the value.
+///
+/// ```
+/// use patchable_core::{Delta, InitPatch, Patchable};
+///
+/// let mut total: Option<i32> = None;
+/// total.apply_patch(InitPatch(Delta(0)));
+/// assert_eq!(total, None);
+///
+/// total.apply_patch(InitPatch(Delta(2)));
+/// total.apply_patch(InitPatch(Delta(3)));
+/// assert_eq!(total, Some(5));
+/// ```
+pub struct InitPatch<P>(pub P);
+
+impl<T, P> Patchable<InitPatch<P>> for Option<T> where T: Patchable<P> + Default, P: Patch {
+    fn apply_patch(&mut self, patch: InitPatch<P>) {
+        if self.is_none() && patch.0.is_empty() {
+            return;
+        }
+        self.get_or_insert_with(T::default).apply_patch(patch.0);
+    }
+}
+
+// Forwarding `Patchable<P>` itself through these pointers would overlap with the blanket impls, such as
+// `Patchable<Option<T>> for T`, so they forward `InnerPatch<P>` instead.
+macro_rules! inner_patch_impl {
+    ($($pointer:ident $(where T: $bound:ident)? => |$this:ident| $inner:expr;)*) => {
+        $(
+            impl<T, P> Patchable<InnerPatch<P>> for $pointer<T> where T: Patchable<P> $(+ $bound)? {
+                fn apply_patch(&mut self, patch: InnerPatch<P>) {
+                    let $this = self;
+                    $inner.apply_patch(patch.0);
+                }
+            }
+        )*
+    };
+}
+
+inner_patch_impl! {
+    RefCell => |this| this.get_mut();
+    Rc where T: Clone => |this| Rc::make_mut(this);
+    Arc where T: Clone => |this| Arc::make_mut(this);
+}
+
+/// A [`Box`] also forwards to unsized values, so a boxed slice, whose length is fixed, is patched by position like a
+/// slice.
+///
+/// ```
+/// use patchable_core::{InnerPatch, PatchError, Patchable, TryPatchable};
+///
+/// let mut values: Box<[i32]> = Box::new([1, 2, 3]);
+/// values.apply_patch(InnerPatch(vec![None, None, Some(Some(30))]));
+/// assert_eq!(*values, [1, 2, 30]);
+///
+/// let result = values.try_apply_patch(InnerPatch(vec![None, None, None, Some(Some(4))]));
+/// assert_eq!(result, Err(PatchError::OutOfRange { offset: 0, len: 4, target_len: 3 }));
+/// ```
+impl<T, P> Patchable<InnerPatch<P>> for Box<T> where T: Patchable<P> + ?Sized {
+    fn apply_patch(&mut self, patch: InnerPatch<P>) {
+        (**self).apply_patch(patch.0);
+    }
+}
+
+impl<T, P> TryPatchable<InnerPatch<P>> for Box<T> where T: TryPatchable<P> + ?Sized {
+    fn try_apply_patch(&mut self, patch: InnerPatch<P>) -> Result<(), PatchError> {
+        (**self).try_apply_patch(patch.0)
+    }
+}
+
+/// Patches the value behind a back-reference if it is still alive, and does nothing if it was dropped.
+///
+/// The value is borrowed mutably while the patch is applied, which panics if it is already borrowed.
+/// `try_apply_patch` returns [`PatchError::Dangling`] if the value was dropped.
+///
+/// ```
+/// use patchable_core::{InnerPatch, PatchError, Patchable, TryPatchable};
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+///
+/// let node = Rc::new(RefCell::new(1));
+/// let mut parent = Rc::downgrade(&node);
+/// parent.apply_patch(InnerPatch(Some(2)));
+/// assert_eq!(*node.borrow(), 2);
+///
+/// drop(node);
+/// parent.apply_patch(InnerPatch(Some(3)));
+/// assert_eq!(parent.try_apply_patch(InnerPatch(Some(3))), Err(PatchError::Dangling));
+/// ```
+impl<T, P> Patchable<InnerPatch<P>> for Weak<RefCell<T>> where T: Patchable<P> {
+    fn apply_patch(&mut self, patch: InnerPatch<P>) {
+        if let Some(target) = self.upgrade() {
+            Patchable::apply_patch(&mut *target.borrow_mut(), patch.0);
+        }
+    }
+}
+
+impl<T, P> TryPatchable<InnerPatch<P>> for Weak<RefCell<T>> where T: Patchable<P> {
+    fn try_apply_patch(&mut self, patch: InnerPatch<P>) -> Result<(), PatchError> {
+        let target = self.upgrade().ok_or(PatchError::Dangling)?;
+        Patchable::apply_patch(&mut *target.borrow_mut(), patch.0);
+        Ok(())
+    }
+}
+
+/// Extension trait for maps, applying the same patch to every value.
+///
+/// Each value gets a clone of the patch, except the last one, which gets the patch itself,
+/// so an empty map does not clone at all.
+///
+/// ```
+/// use patchable_core::{PatchAllValues, Patchable};
+/// use std::collections::HashMap;
+///
+/// struct Player {
+///     online: bool,
+/// }
+///
+/// impl Patchable<bool> for Player {
+///     fn apply_patch(&mut self, online: bool) {
+///         self.online = online;
+///     }
+/// }
+///
+/// let mut players = HashMap::new();
+/// players.insert("a", Player { online: true });
+/// players.insert("b", Player { online: true });
+/// players.patch_all_values(false);
+/// assert!(players.values().all(|player| !player.online));
+/// ```
+pub trait PatchAllValues<P> {
+    fn patch_all_values(&mut self, patch: P);
+}
+
+/// Applies `patch` to every value, cloning it for all but the last.
+fn patch_values<'a, V, P>(values: impl Iterator<Item = &'a mut V>, patch: P) where V: Patchable<P> + 'a, P: Clone {
+    let mut values = values.peekable();
+    while let Some(value) = values.next() {
+        if values.peek().is_none() {
+            value.apply_patch(patch);
+            return;
+        }
+        value.apply_patch(patch.clone());
+    }
+}
+
+impl<K, V, P, S> PatchAllValues<P> for HashMap<K, V, S> where V: Patchable<P>, P: Clone {
+    fn patch_all_values(&mut self, patch: P) {
+        patch_values(self.values_mut(), patch);
+    }
+}
+
+impl<K, V, P> PatchAllValues<P> for BTreeMap<K, V> where V: Patchable<P>, P: Clone {
+    fn patch_all_values(&mut self, patch: P) {
+        patch_values(self.values_mut(), patch);
+    }
+}
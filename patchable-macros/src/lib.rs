@@ -1,13 +1,17 @@
 //! Derive macro(s) for patchable structs in Rust.
-//! You probably want [`patchable`](https://docs.rs/patchable) instead.
+//! You probably want [`patchable`](https://docs.rs/patchable) instead, which re-exports the derive behind its default `derive` feature.
+//! The generated code refers to items through the `patchable` crate.
 
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use syn::*;
+use syn::ext::IdentExt;
 use quote::*;
 
 /// Creates a patch struct and implements [`Patchable`](trait@patchable_core::Patchable).
 /// 
 /// ```
+/// # use patchable::Patchable;
 /// #[derive(Patchable)]
 /// struct MyStruct {
 ///     foo: String,
@@ -15,62 +19,285 @@ use quote::*;
 /// }
 /// ```
 /// would generate 
-/// ```
-/// pub struct MyStructPatch {
-///     pub foo: Option<String>,
-///     pub bar: Option<String>
+/// ```ignore
+/// struct MyStructPatch {
+///     foo: Option<String>,
+///     bar: Option<i32>
 /// }
 /// 
 /// impl Patchable<MyStructPatch> for MyStruct {
 ///     // skipped for brevity
 /// }
 /// ```
-/// 
-/// You can also specify using the `#[patch(PatchType)]` attribute to change both the name of the generated struct,
-/// and the type of the replacement field in the generate struct. This works as long as the field type implements `Patchable<PatchType>`.
-/// 
-/// This allows for nesting of patches.
-/// 
-/// ```
-/// #[derive(Patchable)]
-/// #[patch(MyPatch)]
-/// struct MyStruct {
-///     foo: String,
-///     #[patch(BarPatch)]
-///     bar: Bar,
-/// }
-/// 
-/// #[derive(Patchable)]
-/// struct Bar {
-///     foobar: i32,
-/// }
-/// ```
-/// would generate
+///
+/// Options are given in `#[patch(...)]` attributes, on the struct for the container options and on a field for the
+/// field options. Each of them is described with an example in the
+/// [guide](https://docs.rs/patchable/latest/patchable/guide/index.html) of `patchable`.
+///
+/// | Container option | Effect |
+/// | --- | --- |
+/// | `rename_type = "Type"` | Names the patch struct, which is the struct name followed by `Patch` by default |
+/// | `Type` | Deprecated form of `rename_type = "Type"` |
+/// | `private_fields` | Makes the patch fields private |
+/// | `forward_attrs` | Copies the other attributes of the struct, such as derives, onto the patch struct |
+/// | `bound = "..."` | Adds bounds to the generated items |
+/// | `crate = "path"` | Path of the `patchable` crate, `::patchable` by default |
+/// | `serde` | Derives `Serialize` and `Deserialize` for the patch struct |
+/// | `rename_all = "..."` | Forwards `#[serde(rename_all = "...")]`, requires `serde` |
+/// | `deny_unknown_fields` | Forwards `#[serde(deny_unknown_fields)]`, requires `serde` |
+/// | `untagged` | Forwards `#[serde(untagged)]` onto a patch enum, requires `serde` |
+/// | `schemars` | Derives `schemars::JsonSchema` for the patch struct |
+/// | `target = "Type"` | Applies the patch to another struct with the same field names |
+/// | `from = "Type"` | Implements `From<Type>` for the patch struct |
+/// | `on_apply = "fn"` | Calls a `fn(changed: usize)` after every `apply_patch` |
+/// | `post_apply = "fn"` | Calls a `fn(&mut T)` with the patched value after every `apply_patch` |
+/// | `validate_all = "fn"` | Checks the patched value in `try_apply_patch`, restoring it on failure |
+/// | `order(a, b)` | Applies the listed fields first |
+/// | `changes` | Generates a `NameChange` enum and `apply_patch_changes` |
+/// | `examples` | Generates `NamePatch::example()`, with the `test-util` feature of `patchable` |
+/// | `no_impl` | Leaves out the `Patchable`, `TryPatchable` and `HasPatch` impls |
+/// | `transparent` | Patches a single field struct with the patch of its field |
+/// | `profile(Name, fields(a, b))` | Generates a `NamePatch` struct with only the listed fields |
+///
+/// | Field option | Effect |
+/// | --- | --- |
+/// | `PatchType`, `ty = "Type"` | Patches the field with `PatchType` instead of an `Option` |
+/// | `nested` | Patches the field with the patch named by its `HasPatch` impl |
+/// | `replace` | Replaces the field as a whole through an `Option`, the default |
+/// | `map = "fn", input = "In"` | Converts an incoming `In` with a `fn(In) -> T` |
+/// | `flatten_option` | Patches an `Option<T>` field with an `Option<T>` |
+/// | `list_or_clear` | Patches a `Vec<T>` field with a `ListPatch<T>` |
+/// | `nested_option` | Patches the value inside an `Option<T>` field with an `InnerPatch` |
+/// | `init_default` | With `nested_option`, starts from `T::default()` through an `InitPatch` |
+/// | `rename = "name"` | Names the patch field |
+/// | `alias = "name"` | Forwards `#[serde(alias = "name")]`, requires `serde` |
+/// | `deprecated = "note"` | Marks the patch field `#[deprecated]` |
+/// | `feature = "name"` | Only generates the patch field with the cargo feature `name` |
+/// | `empty_if = "fn"` | Decides whether the patch field is empty |
+/// | `validate(check, "message")` | Checks the incoming value in `try_apply_patch` |
+/// | `min_version = N` | Rejects the field in `check_version` below version `N` |
+/// | `group = "name"` | Only applies the fields of a group together |
+/// | `after = "field"` | Applies the field after another one |
+/// | `inspect = "fn"` | Calls a `fn(&T)` with the field after it is patched |
+/// | `key` | Implements `PatchKey` to return the field |
+///
+/// Enums with unit variants or single field variants can be derived as well, taking the field options on variants.
+#[proc_macro_derive(Patchable, attributes(patch, schemars))]
+pub fn derive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_derive(input) {
+        Ok(output) => TokenStream::from(output),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+/// Creates a patch struct for a type defined elsewhere, such as in another crate, from a list of its fields.
+///
+/// The attribute goes on a unit struct, which becomes the patch struct, and names the patched type with `for` and
+/// its fields with `fields(...)`. Fields are declared like struct fields, and take the same `#[patch(...)]`
+/// attributes as with the derive, as do the patch struct itself and its container options.
+/// The patch struct gets the other attributes of the unit struct, and the visibility of the listed fields.
+/// As the generated impls access the fields directly, only fields visible from the current crate can be listed.
+///
 /// ```
-/// struct MyPatch {
-///     foo: Option<String>,
-///     bar: BarPatch,
+/// mod other {
+///     pub struct Foo {
+///         pub a: i32,
+///         pub b: String,
+///         pub c: bool,
+///     }
 /// }
-/// 
-/// struct BarPatch {
-///     foobar: Option<i32>,
-/// }
-/// // Patchable impls...
+///
+/// # use patchable::Patchable;
+/// #[patchable::patchable(for = "other::Foo", fields(pub a: i32, pub b: String))]
+/// #[derive(Clone, Default)]
+/// pub struct FooPatch;
+///
+/// let mut foo = other::Foo { a: 1, b: String::from("old"), c: true };
+/// let patch = FooPatch { b: Some(String::from("new")), ..FooPatch::default() };
+/// foo.apply_patch(patch.clone());
+/// assert_eq!((foo.a, foo.b.as_str()), (1, "new"));
 /// ```
-#[proc_macro_derive(Patchable, attributes(patch))]
-pub fn derive(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as DeriveInput);
-    let input_name = input.ident;
+#[proc_macro_attribute]
+pub fn patchable(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as ForeignArgs);
+    let item = parse_macro_input!(item as ItemStruct);
+    match expand_foreign(args, item) {
+        Ok(output) => TokenStream::from(output),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
 
-    let patch_name = if let Some(name) = parse_attrs_ident(input.attrs) {
-        name
-    } else {
-        format_ident!("{}Patch", input_name)
+/// Arguments of the `#[patchable(for = "...", fields(...))]` attribute.
+struct ForeignArgs {
+    /// The patched type, kept as a string for `#[patch(target = "...")]`.
+    target: LitStr,
+    fields: punctuated::Punctuated<Field, Token![,]>,
+}
+
+impl parse::Parse for ForeignArgs {
+    fn parse(input: parse::ParseStream) -> Result<Self> {
+        let mut target = None;
+        let mut fields = None;
+        while !input.is_empty() {
+            if input.peek(Token![for]) {
+                input.parse::<Token![for]>()?;
+                input.parse::<Token![=]>()?;
+                let lit: LitStr = input.parse()?;
+                lit.parse::<Type>()?;
+                target = Some(lit);
+            } else {
+                let ident: Ident = input.parse()?;
+                if ident != "fields" {
+                    return Err(Error::new_spanned(ident, "expected `for = \"...\"` or `fields(...)`"));
+                }
+                let content;
+                parenthesized!(content in input);
+                fields = Some(content.parse_terminated(Field::parse_named)?);
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        match (target, fields) {
+            (Some(target), Some(fields)) => Ok(ForeignArgs { target, fields }),
+            _ => Err(input.error("expected `for = \"...\"` and `fields(...)`")),
+        }
+    }
+}
+
+/// Expands `#[patchable(...)]` as the derive on a struct with the listed fields, targeting the foreign type.
+fn expand_foreign(args: ForeignArgs, item: ItemStruct) -> Result<TokenStream2> {
+    if !matches!(item.fields, Fields::Unit) {
+        return Err(Error::new_spanned(item.fields, "the fields of the patched type are listed in `fields(...)`, the patch struct must be a unit struct"));
+    }
+    let ItemStruct { mut attrs, vis, struct_token, ident, generics, .. } = item;
+    let target = args.target;
+    let name = LitStr::new(&ident.to_string(), ident.span());
+    attrs.push(parse_quote!{#[patch(rename_type = #name, target = #target, forward_attrs)]});
+    let input = DeriveInput {
+        attrs,
+        vis,
+        ident,
+        generics,
+        data: Data::Struct(DataStruct {
+            struct_token,
+            fields: Fields::Named(FieldsNamed { brace_token: token::Brace::default(), named: args.fields }),
+            semi_token: None,
+        }),
     };
+    expand_derive(input)
+}
 
-    let mut names = Vec::new();
-    let mut types = Vec::new();
-    let mut patch_types = Vec::new();
+fn expand_derive(input: DeriveInput) -> Result<TokenStream2> {
+    // Each container attribute naming a patch generates its own patch, with the attributes that do not name one.
+    // Only the first implements `HasPatch`, which can only name one patch type.
+    let mut named = Vec::new();
+    let mut output = TokenStream2::new();
+    for (index, attr) in input.attrs.iter().enumerate() {
+        if attr.path.is_ident("patch") {
+            let container = parse_container_attrs(std::slice::from_ref(attr))?;
+            if let Some(name) = &container.positional_name {
+                output.extend(positional_name_warning(name));
+            }
+            if container.name.is_some() {
+                named.push(index);
+            }
+        }
+    }
+    if named.len() < 2 {
+        output.extend(expand_patches(input, true)?);
+        return Ok(output);
+    }
+    for &index in &named {
+        let mut single = input.clone();
+        single.attrs = input.attrs.iter().enumerate()
+            .filter(|(other, _)| *other == index || !named.contains(other))
+            .map(|(_, attr)| attr.clone())
+            .collect();
+        output.extend(expand_patches(single, index == named[0])?);
+    }
+    Ok(output)
+}
+
+/// Error for the structs and unions that the derive does not support.
+const UNSUPPORTED_DATA: &str = "Patchable can only be derived for structs with named fields (or `transparent` newtypes)";
+
+/// Returns whether `attrs` derive a trait named `name`, by any path.
+fn derives(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().filter(|attr| attr.path.is_ident("derive")).any(|attr| match attr.parse_meta() {
+        Ok(Meta::List(list)) => list.nested.iter().any(|nested| match nested {
+            NestedMeta::Meta(Meta::Path(path)) => path.segments.last().map(|segment| segment.ident == name).unwrap_or(false),
+            _ => false,
+        }),
+        _ => false,
+    })
+}
+
+/// Makes the compiler warn at a bare `#[patch(Name)]` on the struct, through a use of a deprecated constant.
+fn positional_name_warning(name: &Ident) -> TokenStream2 {
+    let note = format!("use `#[patch(rename_type = \"{}\")]` instead of the bare name", name);
+    quote!{
+        const _: () = {
+            #[deprecated(note = #note)]
+            #[allow(non_upper_case_globals)]
+            const #name: () = ();
+            #name
+        };
+    }
+}
+
+/// Expands the derive for one set of container attributes, implementing `HasPatch` if `has_patch` is set.
+fn expand_patches(input: DeriveInput, has_patch: bool) -> Result<TokenStream2> {
+    let container = parse_container_attrs(&input.attrs)?;
+    let krate = container.krate.clone().unwrap_or_else(|| parse_quote!{::patchable});
+    if container.transparent.is_some() {
+        return expand_transparent(&krate, &input, &container, has_patch);
+    }
+    if container.nested {
+        return Err(Error::new_spanned(&input.ident, "`nested` on a struct requires `transparent`"));
+    }
+
+    let mut struct_attrs: Vec<TokenStream2> = Vec::new();
+    let clone_forwarded = container.forward_attrs && derives(&input.attrs, "Clone");
+    if container.forward_attrs {
+        struct_attrs.extend(input.attrs.iter().filter(|attr| !attr.path.is_ident("patch")).map(ToTokens::to_token_stream));
+    }
+    if container.serde {
+        struct_attrs.push(quote!{#[derive(::serde::Serialize, ::serde::Deserialize)]});
+    }
+    if let Some(deny_unknown_fields) = &container.deny_unknown_fields {
+        if !container.serde {
+            return Err(Error::new_spanned(deny_unknown_fields, "`deny_unknown_fields` requires #[patch(serde)]"));
+        }
+        struct_attrs.push(quote!{#[serde(deny_unknown_fields)]});
+    }
+    if let Some(untagged) = &container.untagged {
+        if !matches!(input.data, Data::Enum(_)) {
+            return Err(Error::new_spanned(untagged, "`untagged` only applies to enums"));
+        }
+        if !container.serde {
+            return Err(Error::new_spanned(untagged, "`untagged` requires #[patch(serde)]"));
+        }
+        struct_attrs.push(quote!{#[serde(untagged)]});
+    }
+    if container.schemars {
+        struct_attrs.push(quote!{#[derive(::schemars::JsonSchema)]});
+    }
+    if let Some(rename_all) = &container.rename_all {
+        if !container.serde {
+            return Err(Error::new_spanned(rename_all, "`rename_all` requires #[patch(serde)]"));
+        }
+        struct_attrs.push(quote!{#[serde(rename_all = #rename_all)]});
+    }
+
+    let mut generics = input.generics.clone();
+    if !container.bounds.is_empty() {
+        generics.make_where_clause().predicates.extend(container.bounds.iter().cloned());
+    }
+
+    let mut fields = Vec::new();
+    let mut key = None;
 
     match input.data {
         Data::Struct(struct_data) => {
@@ -78,63 +305,1896 @@ pub fn derive(input: TokenStream) -> TokenStream {
                 Fields::Named(named_fields) => {
                     for field in named_fields.named {
                         let ty = field.ty;
-                        names.push(field.ident.unwrap());
-                        types.push(quote!{#ty});
-                        if let Some(ident) = parse_attrs_ident(field.attrs) {
-                            patch_types.push(quote!{#ident});
-                        } else {
-                            patch_types.push(quote!{::core::option::Option<#ty>});
+                        let field_attrs = parse_field_attrs(&field.attrs)?;
+                        let name = field.ident.unwrap();
+                        if let Some(path) = field_attrs.key {
+                            if key.is_some() {
+                                return Err(Error::new_spanned(path, "only one field can be the `key`"));
+                            }
+                            key = Some((name.clone(), ty.clone()));
+                        }
+                        let replaces = matches!(field_attrs.kind, None | Some(FieldKind::Replace) | Some(FieldKind::FlattenOption));
+                        let flatten_option = matches!(field_attrs.kind, Some(FieldKind::FlattenOption));
+                        let nested = matches!(field_attrs.kind, Some(FieldKind::Nested));
+                        if !replaces && !field_attrs.validators.is_empty() {
+                            return Err(Error::new_spanned(name, "`validate` requires the field to be patched with an `Option`"));
+                        }
+                        // A field holding a `&mut T` is patched through the reference, with the patch type of `T`.
+                        let (ty, reborrow) = match ty {
+                            Type::Reference(reference) if !replaces => {
+                                if reference.mutability.is_none() {
+                                    return Err(Error::new_spanned(reference, "a shared reference cannot be patched through; use `&mut T`, or replace the whole reference without a patch type"));
+                                }
+                                (*reference.elem, true)
+                            },
+                            ty => (ty, false),
+                        };
+                        let patch_type = match (&field_attrs.map, field_attrs.input) {
+                            (Some(_), Some(input)) if matches!(field_attrs.kind, None | Some(FieldKind::Replace)) => {
+                                quote!{::core::option::Option<#input>}
+                            },
+                            (None, None) => field_patch_type(&krate, &ty, field_attrs.kind)?,
+                            _ => return Err(Error::new_spanned(name, "`map` needs `input`, and replaces the field without another patch type")),
+                        };
+                        let patch_field = field_attrs.rename.as_ref().map_or(Ok(name.clone()), LitStr::parse)?;
+                        if let Some(other) = fields.iter().find(|other: &&PatchField| other.patch_field.unraw() == patch_field.unraw()) {
+                            let message = format!("patch field `{}` is already generated for field `{}`", patch_field.unraw(), other.name.unraw());
+                            return Err(match &field_attrs.rename {
+                                Some(rename) => Error::new_spanned(rename, message),
+                                None => Error::new_spanned(&name, message),
+                            });
+                        }
+                        if let (false, Some(alias)) = (container.serde, field_attrs.aliases.first()) {
+                            return Err(Error::new_spanned(alias, "`alias` requires #[patch(serde)]"));
                         }
+                        let mut attrs: Vec<TokenStream2> = field_attrs.deprecated.into_iter().collect();
+                        attrs.extend(field_attrs.aliases.iter().map(|alias| quote!{#[serde(alias = #alias)]}));
+                        if let (true, Some(empty_if)) = (container.serde, &field_attrs.empty_if) {
+                            let empty_if = LitStr::new(&empty_if.to_token_stream().to_string().replace(' ', ""), name.span());
+                            attrs.push(quote!{#[serde(skip_serializing_if = #empty_if)]});
+                        }
+                        if container.schemars {
+                            attrs.extend(field.attrs.iter().filter(|attr| attr.path.is_ident("schemars")).map(ToTokens::to_token_stream));
+                        }
+                        fields.push(PatchField {
+                            vis: if container.private_fields { Visibility::Inherited } else { field.vis },
+                            ty,
+                            name,
+                            patch_field,
+                            replaces,
+                            patch_type,
+                            attrs,
+                            validators: field_attrs.validators,
+                            empty_if: field_attrs.empty_if,
+                            reborrow,
+                            flatten_option,
+                            nested,
+                            map: field_attrs.map,
+                            feature: field_attrs.feature,
+                            inspect: field_attrs.inspect,
+                            min_version: field_attrs.min_version,
+                            group: field_attrs.group,
+                            after: field_attrs.after,
+                        });
                     }
                 },
-                Fields::Unnamed(_unnamed_fields) => unimplemented!(),
-                Fields::Unit => unimplemented!(),
+                Fields::Unnamed(_) | Fields::Unit => return Err(Error::new_spanned(&input.ident, UNSUPPORTED_DATA)),
             }
         },
-        Data::Enum(_enum_data) => unimplemented!(),
-        Data::Union(_union_data) => unimplemented!(),
+        Data::Enum(enum_data) => {
+            let (_, ty_generics, _) = generics.split_for_impl();
+            let input_name = &input.ident;
+            let source = Source {
+                krate: &krate,
+                vis: &input.vis,
+                target: quote!{#input_name #ty_generics},
+                generics: &generics,
+                struct_attrs: &struct_attrs,
+                order: &container.order,
+                from: &container.from,
+                on_apply: container.on_apply.as_ref(),
+                post_apply: container.post_apply.as_ref(),
+                validate_all: container.validate_all.as_ref(),
+                no_impl: container.no_impl,
+                examples: container.examples.is_some(),
+                clone_forwarded,
+            };
+            return expand_enum(&source, input_name, &container, enum_data, has_patch);
+        },
+        Data::Union(_) => return Err(Error::new_spanned(&input.ident, UNSUPPORTED_DATA)),
+    }
+
+    for name in &container.order {
+        if !fields.iter().any(|field| field.name == *name) {
+            return Err(Error::new_spanned(name, format!("no field named `{}`", name)));
+        }
+    }
+    for name in fields.iter().flat_map(|field| &field.after) {
+        if !fields.iter().any(|field| field.name == *name) {
+            return Err(Error::new_spanned(name, format!("no field named `{}`", name)));
+        }
+    }
+    // Profiles apply subsets of the fields, so checking all of them covers every cycle.
+    let all_fields: Vec<&PatchField> = fields.iter().collect();
+    if let Err(cycle) = apply_order(&all_fields, &container.order) {
+        let names: Vec<String> = cycle.iter().chain(cycle.first()).map(|field| format!("`{}`", field.name)).collect();
+        let message = format!("the `after` dependencies of `{}` form a cycle: {}", cycle[0].name, names.join(" -> "));
+        return Err(Error::new_spanned(&cycle[0].name, message));
+    }
+
+    let input_name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let target = match &container.target {
+        Some(target) => quote!{#target},
+        None => quote!{#input_name #ty_generics},
+    };
+
+    // Container attributes without a name are shared by every named patch, so a profile would be generated for each.
+    if let (Some(name), false) = (&container.name, container.profiles.is_empty()) {
+        return Err(Error::new_spanned(name, "`profile` names its own patch structs and cannot be combined with a patch name"));
+    }
+    if container.changes && (container.target.is_some() || !container.profiles.is_empty()) {
+        return Err(Error::new_spanned(input_name, "`changes` cannot be used with `target` or `profile`"));
+    }
+
+    // Like `HasPatch`, `PatchKey` is only implemented once, and only for the source struct.
+    let mut key_impl = TokenStream2::new();
+    if let Some((key_name, key_type)) = key {
+        if container.target.is_some() {
+            return Err(Error::new_spanned(key_name, "`key` cannot be used with `target`"));
+        }
+        if has_patch {
+            key_impl = quote!{
+                impl #impl_generics #krate::PatchKey for #input_name #ty_generics #where_clause {
+                    type Key = #key_type;
+
+                    fn patch_key(&self) -> &#key_type {
+                        &self.#key_name
+                    }
+                }
+            };
+        }
+    }
+
+    let source = Source {
+        krate: &krate,
+        vis: &input.vis,
+        target,
+        generics: &generics,
+        struct_attrs: &struct_attrs,
+        order: &container.order,
+        from: &container.from,
+        on_apply: container.on_apply.as_ref(),
+        post_apply: container.post_apply.as_ref(),
+        validate_all: container.validate_all.as_ref(),
+        no_impl: container.no_impl,
+        examples: container.examples.is_some(),
+        clone_forwarded,
+    };
+
+    if container.profiles.is_empty() {
+        let patch_name = if let Some(name) = &container.name {
+            name.clone()
+        } else {
+            format_ident!("{}Patch", input.ident)
+        };
+        let mut output = expand_patch(&source, &patch_name, &all_fields);
+
+        if has_patch && container.target.is_none() && !container.no_impl {
+            let struct_generics = patch_struct_generics(&generics, &all_fields);
+            let (_, struct_ty_generics, _) = struct_generics.split_for_impl();
+            output.extend(quote!{
+                impl #impl_generics #krate::HasPatch for #input_name #ty_generics #where_clause {
+                    type Patch = #patch_name #struct_ty_generics;
+                }
+            });
+        }
+        if has_patch && container.changes {
+            output.extend(expand_changes(&source, input_name, &patch_name, &all_fields));
+        }
+        output.extend(key_impl);
+        return Ok(output);
+    }
+
+    let mut output = key_impl;
+    for profile in &container.profiles {
+        let mut profile_fields = Vec::new();
+        for name in &profile.fields {
+            match fields.iter().find(|field| field.name == *name) {
+                Some(field) => profile_fields.push(field),
+                None => return Err(Error::new_spanned(name, format!("no field named `{}`", name))),
+            }
+        }
+        let patch_name = format_ident!("{}Patch", profile.name);
+        output.extend(expand_patch(&source, &patch_name, &profile_fields));
+    }
+    Ok(output)
+}
+
+/// The parts of the source struct shared by every patch generated from it.
+struct Source<'a> {
+    /// Path to the `patchable` crate, `::patchable` unless set with `#[patch(crate = "...")]`.
+    krate: &'a Path,
+    vis: &'a Visibility,
+    /// The type the `Patchable` impls are for, the source struct unless set with `#[patch(target = "...")]`.
+    target: TokenStream2,
+    /// Generics of the source struct, including any `#[patch(bound = "...")]` predicates.
+    generics: &'a Generics,
+    /// Attributes for the patch struct, from `forward_attrs` and the serde options.
+    struct_attrs: &'a [TokenStream2],
+    order: &'a [Ident],
+    /// Types to implement `From` for the patch struct, from `#[patch(from = "...")]`.
+    from: &'a [Type],
+    on_apply: Option<&'a Path>,
+    /// Called with the patched value at the end of `apply_patch`, from `#[patch(post_apply = "...")]`.
+    post_apply: Option<&'a Path>,
+    /// Checks the patched value in `try_apply_patch`, from `#[patch(validate_all = "...")]`.
+    validate_all: Option<&'a Path>,
+    /// Set by `#[patch(no_impl)]`, which leaves out the `Patchable` and `TryPatchable` impls.
+    no_impl: bool,
+    /// Set by `#[patch(examples)]`, which generates `example()` for the patch struct.
+    examples: bool,
+    /// Whether `forward_attrs` puts a derive of `Clone` on the patch struct, which then gets no `Clone` impl of ours.
+    clone_forwarded: bool,
+}
+
+/// A field of the source struct, as it appears in the patch struct.
+struct PatchField {
+    vis: Visibility,
+    /// Type of the patched value, the referenced type for a field holding a `&mut T`.
+    ty: Type,
+    /// Name of the field in the source struct.
+    name: Ident,
+    /// Name of the field in the patch struct, which differs from `name` with `#[patch(rename = "...")]`.
+    patch_field: Ident,
+    /// Whether the patch field is an `Option` of the field type.
+    replaces: bool,
+    patch_type: TokenStream2,
+    attrs: Vec<TokenStream2>,
+    /// Checks on the incoming value, only present on fields patched with an `Option`.
+    validators: Vec<Validator>,
+    /// Replaces `Patch::is_empty` for this field.
+    empty_if: Option<Path>,
+    /// Whether the source field is a `&mut T`, patched through the reference.
+    reborrow: bool,
+    /// Whether the source field is an `Option<T>` patched with an `Option<T>`, from `#[patch(flatten_option)]`.
+    flatten_option: bool,
+    /// Whether the field is patched with its type's `HasPatch::Patch`, from `#[patch(nested)]`.
+    nested: bool,
+    /// Converts the incoming value before it replaces the field, from `#[patch(map = "...")]`.
+    map: Option<Path>,
+    /// Cargo feature of the user's crate that the patch field only exists with, from `#[patch(feature = "...")]`.
+    feature: Option<LitStr>,
+    /// Called with the field after it is patched with a patch that is not empty, from `#[patch(inspect = "...")]`.
+    inspect: Option<Path>,
+    /// First client version the field may be set from, from `#[patch(min_version = ...)]`.
+    min_version: Option<LitInt>,
+    /// Set of fields only applied together, from `#[patch(group = "...")]`.
+    group: Option<LitStr>,
+    /// Fields of the source struct this one is applied after, from `#[patch(after = "...")]`.
+    after: Vec<Ident>,
+}
+
+impl PatchField {
+    /// Returns the `#[cfg(...)]` attribute for everything generated for this field, if it has a `feature`.
+    fn cfg(&self) -> TokenStream2 {
+        match &self.feature {
+            Some(feature) => quote!{#[cfg(feature = #feature)]},
+            None => TokenStream2::new(),
+        }
     }
+}
+
+fn expand_patch(source: &Source, patch_name: &Ident, fields: &[&PatchField]) -> TokenStream2 {
+    let krate = source.krate;
+    let input_vis = source.vis;
+    let target = &source.target;
+    let struct_attrs = source.struct_attrs;
+    let generics = source.generics;
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let struct_generics = patch_struct_generics(generics, fields);
+    let (struct_impl_generics, ty_generics, struct_where_clause) = struct_generics.split_for_impl();
+
+    let patch_fields: Vec<&Ident> = fields.iter().map(|field| &field.patch_field).collect();
+    let cfgs: Vec<TokenStream2> = fields.iter().map(|field| field.cfg()).collect();
+    let vises: Vec<&Visibility> = fields.iter().map(|field| &field.vis).collect();
+
+    let groups = FieldGroups::new(fields);
+    let group_checks = groups.checks(krate, quote!{patch});
+
+    // `expand_patches` has already rejected cycles of `after` dependencies.
+    let apply_fields = apply_order(fields, source.order).unwrap_or_else(|_| fields.to_vec());
+    let applies: Vec<TokenStream2> = apply_fields.iter().map(|field| {
+        let name = &field.name;
+        let patch_field = &field.patch_field;
+        let cfg = field.cfg();
+        let (target, value) = if field.reborrow {
+            (quote!{&mut *self.#name}, quote!{&*self.#name})
+        } else {
+            (quote!{&mut self.#name}, quote!{&self.#name})
+        };
+        let patch_value = match &field.map {
+            Some(map) => quote!{::core::option::Option::map(patch.#patch_field, #map)},
+            None if field.flatten_option => quote!{::core::option::Option::map(patch.#patch_field, ::core::option::Option::Some)},
+            None => quote!{patch.#patch_field},
+        };
+        let apply = quote!{#krate::Patchable::apply_patch(#target, #patch_value);};
+        let apply = match &field.inspect {
+            Some(inspect) => {
+                let is_empty = field_is_empty(krate, field, quote!{patch});
+                quote!{
+                    {
+                        let changed = !#is_empty;
+                        #apply
+                        if changed {
+                            #inspect(#value);
+                        }
+                    }
+                }
+            },
+            None => apply,
+        };
+        match groups.var(field) {
+            Some(group_var) => quote!{#cfg if #group_var { #apply }},
+            None => quote!{#cfg #apply},
+        }
+    }).collect();
+    let patch_types: Vec<&TokenStream2> = fields.iter().map(|field| &field.patch_type).collect();
+    let field_attrs: Vec<&Vec<TokenStream2>> = fields.iter().map(|field| &field.attrs).collect();
 
     let patch_struct = quote!{
-        pub struct #patch_name {
-            #(pub #names: #patch_types),*
+        #(#struct_attrs)*
+        #input_vis struct #patch_name #struct_generics #struct_where_clause {
+            #(#cfgs #(#field_attrs)* #vises #patch_fields: #patch_types),*
         }
     };
 
+    let (count_changed, on_apply) = match source.on_apply {
+        Some(on_apply) => {
+            let changed = fields.iter().map(|field| {
+                let is_empty = groups.is_empty(krate, field, quote!{patch});
+                quote!{!#is_empty as usize}
+            });
+            (quote!{let changed = 0 #(+ #changed)*;}, quote!{#on_apply(changed);})
+        },
+        None => (TokenStream2::new(), TokenStream2::new()),
+    };
+    let post_apply = source.post_apply.map(|post_apply| quote!{#post_apply(self);});
+
     let patchable_impl = quote!{
-        impl ::patchable_core::Patchable<#patch_name> for #input_name {
-            fn apply_patch(&mut self, patch: #patch_name) {
-                #(
-                    self.#names.apply_patch(patch.#names);
-                )*
+        #[allow(deprecated)]
+        impl #impl_generics #krate::Patchable<#patch_name #ty_generics> for #target #where_clause {
+            fn apply_patch(&mut self, patch: #patch_name #ty_generics) {
+                #group_checks
+                #count_changed
+                #(#applies)*
+                #post_apply
+                #on_apply
+            }
+        }
+    };
+
+    let mut checks = Vec::new();
+    for field in fields {
+        let patch_field = &field.patch_field;
+        let field_name = LitStr::new(&patch_field.unraw().to_string(), patch_field.span());
+        let cfg = field.cfg();
+        for Validator { check, message } in &field.validators {
+            checks.push(quote!{
+                #cfg
+                if let ::core::option::Option::Some(value) = &patch.#patch_field {
+                    if !__patchable_check(value, #check) {
+                        return ::core::result::Result::Err(#krate::PatchError::Validation {
+                            field: #field_name,
+                            message: #message,
+                        });
+                    }
+                }
+            });
+        }
+    }
+
+    for ((group, members), var) in groups.groups.iter().zip(&groups.vars) {
+        let counts = members.iter().map(|field| {
+            let cfg = field.cfg();
+            let is_empty = field_is_empty(krate, field, quote!{patch});
+            quote!{
+                #cfg
+                {
+                    total += 1;
+                    set += !#is_empty as usize;
+                }
+            }
+        });
+        let missing = members.iter().map(|field| {
+            let cfg = field.cfg();
+            let name = LitStr::new(&field.patch_field.unraw().to_string(), field.patch_field.span());
+            let is_empty = field_is_empty(krate, field, quote!{patch});
+            quote!{
+                #cfg
+                if #is_empty {
+                    missing.push(#name);
+                }
+            }
+        });
+        checks.push(quote!{
+            let #var = {
+                #[allow(unused_mut)]
+                let mut total = 0usize;
+                #[allow(unused_mut)]
+                let mut set = 0usize;
+                #(#counts)*
+                set == 0 || set == total
+            };
+            if !#var {
+                #[allow(unused_mut)]
+                let mut missing = ::std::vec::Vec::new();
+                #(#missing)*
+                return ::core::result::Result::Err(#krate::PatchError::PartialGroup { group: #group, missing });
+            }
+        });
+    }
+
+    // With `validate_all`, the patch is applied to the value itself, which is restored from a clone if it fails.
+    let apply = match source.validate_all {
+        Some(validate_all) => quote!{
+            let original = ::core::clone::Clone::clone(self);
+            #krate::Patchable::apply_patch(self, patch);
+            if let ::core::result::Result::Err(err) = #validate_all(self) {
+                *self = original;
+                return ::core::result::Result::Err(::core::convert::Into::into(err));
             }
+        },
+        None => quote!{#krate::Patchable::apply_patch(self, patch);},
+    };
+
+    let try_patchable_impl = quote!{
+        #[allow(deprecated)]
+        impl #impl_generics #krate::TryPatchable<#patch_name #ty_generics> for #target #where_clause {
+            fn try_apply_patch(&mut self, patch: #patch_name #ty_generics) -> ::core::result::Result<(), #krate::PatchError> {
+                #[allow(dead_code)]
+                fn __patchable_check<T>(value: &T, check: fn(&T) -> bool) -> bool {
+                    check(value)
+                }
+                #(#checks)*
+                #apply
+                ::core::result::Result::Ok(())
+            }
+        }
+    };
+
+    // The bounds are higher-ranked so that they are only checked where the impl is used: a field whose patch type
+    // does not implement `Patch` makes the impl unavailable instead of failing to compile.
+    // Fields with `empty_if` are created with `Default` instead of `Patch::empty`.
+    let mut patch_generics = struct_generics.clone();
+    let mut empty_values = Vec::new();
+    let mut clears = Vec::new();
+    let mut change_counts = Vec::new();
+    let mut empty_checks = Vec::new();
+    for field in fields {
+        let patch_type = &field.patch_type;
+        let patch_field = &field.patch_field;
+        let cfg = field.cfg();
+        if field.empty_if.is_some() {
+            patch_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #patch_type: ::core::default::Default});
+            empty_values.push(quote!{::core::default::Default::default()});
+            clears.push(quote!{#cfg self.#patch_field = ::core::default::Default::default();});
+            let is_empty = groups.is_empty(krate, field, quote!{self});
+            change_counts.push(quote!{!#is_empty as usize});
+        } else {
+            patch_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #patch_type: #krate::Patch});
+            empty_values.push(quote!{#krate::Patch::empty()});
+            clears.push(quote!{#cfg #krate::Patch::clear(&mut self.#patch_field);});
+            let count = match &field.feature {
+                Some(feature) => quote!{{
+                    #[cfg(feature = #feature)]
+                    let count = #krate::Patch::count_changes(&self.#patch_field);
+                    #[cfg(not(feature = #feature))]
+                    let count = 0;
+                    count
+                }},
+                None => quote!{#krate::Patch::count_changes(&self.#patch_field)},
+            };
+            change_counts.push(match groups.var(field) {
+                Some(var) => quote!{if #var { #count } else { 0 }},
+                None => count,
+            });
+        }
+        empty_checks.push(field_is_empty(krate, field, quote!{self}));
+    }
+    let (patch_impl_generics, _, patch_where_clause) = patch_generics.split_for_impl();
+    let count_group_checks = groups.checks(krate, quote!{self});
+
+    let mut version_checks = Vec::new();
+    for field in fields {
+        if let Some(min_version) = &field.min_version {
+            let patch_field = &field.patch_field;
+            let field_name = LitStr::new(&patch_field.unraw().to_string(), patch_field.span());
+            let cfg = field.cfg();
+            let is_empty = field_is_empty(krate, field, quote!{self});
+            version_checks.push(quote!{
+                #cfg
+                if version < #min_version && !#is_empty {
+                    return ::core::result::Result::Err(#krate::PatchError::UnsupportedField {
+                        field: #field_name,
+                        min_version: #min_version,
+                        version,
+                    });
+                }
+            });
         }
+    }
+
+    let field_count = fields.len();
+    let index_names: Vec<Ident> = fields.iter().map(|field| {
+        let name = field.patch_field.unraw().to_string().to_uppercase();
+        format_ident!("{}_INDEX", name, span = field.patch_field.span())
+    }).collect();
+    let indices = 0..field_count;
+
+    let inherent_impl = quote!{
+        #[allow(deprecated)]
+        impl #struct_impl_generics #patch_name #ty_generics #struct_where_clause {
+            /// The number of fields of the patch struct.
+            pub const FIELD_COUNT: usize = #field_count;
+
+            #(
+                #cfgs
+                #[doc = "Index of the patch field in declaration order, counting fields left out by `feature` too."]
+                pub const #index_names: usize = #indices;
+            )*
+
+            /// Checks that every field set in this patch is accepted from a client of `version`.
+            pub fn check_version(&self, version: u32) -> ::core::result::Result<(), #krate::PatchError> {
+                #(#version_checks)*
+                ::core::result::Result::Ok(())
+            }
+        }
+    };
+
+    let patch_impl = quote!{
+        #[allow(deprecated)]
+        impl #patch_impl_generics #krate::Patch for #patch_name #ty_generics #patch_where_clause {
+            fn empty() -> Self {
+                #patch_name {
+                    #(#cfgs #patch_fields: #empty_values),*
+                }
+            }
+
+            fn is_empty(&self) -> bool {
+                true #(&& #empty_checks)*
+            }
+
+            fn clear(&mut self) {
+                #(#clears)*
+            }
+
+            fn count_changes(&self) -> usize {
+                #count_group_checks
+                0 #(+ #change_counts)*
+            }
+        }
+
+        impl #patch_impl_generics #patch_name #ty_generics #patch_where_clause {
+            /// Creates a patch that changes nothing, the same as `Patch::empty`.
+            pub fn unchanged() -> Self {
+                #krate::Patch::empty()
+            }
+
+            /// Returns `true` if applying this patch would change nothing, the same as `Patch::is_empty`.
+            pub fn is_noop(&self) -> bool {
+                #krate::Patch::is_empty(self)
+            }
+
+            /// Returns the number of fields that are not empty.
+            pub fn fields_set(&self) -> usize {
+                0 #(+ !#empty_checks as usize)*
+            }
+        }
+    };
+
+    // The example sets the first field that is replaced or nested, and not behind a `feature`, so that it is the same
+    // on every build. A replaced field gets the default value, a nested one the example of its patch struct.
+    let mut example_impl = TokenStream2::new();
+    if source.examples {
+        let mut example_generics = patch_generics.clone();
+        let example_field = fields.iter().find(|field| field.feature.is_none() && (field.replaces || field.nested));
+        let example_value = example_field.map(|field| {
+            let patch_field = &field.patch_field;
+            let patch_type = &field.patch_type;
+            // The default value is of the type inside the patch field's `Option`, such as the `input` type with `map`.
+            let value_type = syn::parse2::<Type>(patch_type.clone()).ok()
+                .and_then(|patch_type| generic_argument(&patch_type, "Option").cloned())
+                .unwrap_or_else(|| field.ty.clone());
+            if field.replaces {
+                example_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #value_type: ::core::default::Default});
+                quote!{#patch_field: ::core::option::Option::Some(::core::default::Default::default()),}
+            } else {
+                quote!{#patch_field: <#patch_type>::example(),}
+            }
+        });
+        let (example_impl_generics, _, example_where_clause) = example_generics.split_for_impl();
+        example_impl = quote!{
+            #[allow(deprecated)]
+            impl #example_impl_generics #patch_name #ty_generics #example_where_clause {
+                /// Creates a patch for tests that sets one field, the same one on every call.
+                pub fn example() -> Self {
+                    #patch_name {
+                        #example_value
+                        ..#krate::Patch::empty()
+                    }
+                }
+            }
+        };
+    }
+
+    // Replaced fields take the incoming value if there is one, other fields are merged with their own `Patchable` impl.
+    let mut merge_generics = struct_generics.clone();
+    let mut merges = Vec::new();
+    for field in fields {
+        let patch_type = &field.patch_type;
+        let patch_field = &field.patch_field;
+        let cfg = field.cfg();
+        if field.replaces {
+            merges.push(quote!{
+                #cfg
+                if patch.#patch_field.is_some() {
+                    self.#patch_field = patch.#patch_field;
+                }
+            });
+        } else {
+            merge_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #patch_type: #krate::Patchable<#patch_type>});
+            merges.push(quote!{#cfg #krate::Patchable::apply_patch(&mut self.#patch_field, patch.#patch_field);});
+        }
+    }
+    let (merge_impl_generics, _, merge_where_clause) = merge_generics.split_for_impl();
+
+    let merge_impl = quote!{
+        #[allow(deprecated)]
+        impl #merge_impl_generics #krate::Patchable<#patch_name #ty_generics> for #patch_name #ty_generics #merge_where_clause {
+            fn apply_patch(&mut self, patch: #patch_name #ty_generics) {
+                #(#merges)*
+            }
+        }
+    };
+
+    // Folding starts from `Patch::empty`, so it needs the bounds of both impls.
+    let mut collect_generics = merge_generics.clone();
+    if let Some(patch_where) = &patch_generics.where_clause {
+        collect_generics.make_where_clause().predicates.extend(patch_where.predicates.iter().cloned());
+    }
+    let (collect_impl_generics, _, collect_where_clause) = collect_generics.split_for_impl();
+
+    let collect_impl = quote!{
+        impl #collect_impl_generics ::core::iter::FromIterator<#patch_name #ty_generics> for #patch_name #ty_generics #collect_where_clause {
+            fn from_iter<I: ::core::iter::IntoIterator<Item = #patch_name #ty_generics>>(patches: I) -> Self {
+                let mut merged: Self = #krate::Patch::empty();
+                for patch in patches {
+                    #krate::Patchable::apply_patch(&mut merged, patch);
+                }
+                merged
+            }
+        }
+    };
+
+    let mut visit_generics = struct_generics.clone();
+    let mut visits = Vec::new();
+    for field in fields {
+        let patch_type = &field.patch_type;
+        let patch_field = &field.patch_field;
+        let field_name = LitStr::new(&patch_field.unraw().to_string(), patch_field.span());
+        let cfg = field.cfg();
+        visit_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #patch_type: #krate::VisitPatch});
+        visits.push(quote!{
+            #cfg
+            {
+                let name = if name.is_empty() {
+                    ::std::borrow::Cow::Borrowed(#field_name)
+                } else {
+                    ::std::borrow::Cow::Owned(::std::format!("{}.{}", name, #field_name))
+                };
+                #krate::VisitPatch::visit_set_as(&self.#patch_field, &name, visitor);
+            }
+        });
+    }
+    let (visit_impl_generics, _, visit_where_clause) = visit_generics.split_for_impl();
+
+    let visit_impl = quote!{
+        #[allow(deprecated)]
+        impl #visit_impl_generics #krate::VisitPatch for #patch_name #ty_generics #visit_where_clause {
+            fn visit_set_as<V: #krate::PatchVisitor>(&self, name: &str, visitor: &mut V) {
+                #(#visits)*
+            }
+        }
+    };
+
+    let mut clone_generics = struct_generics.clone();
+    for patch_type in &patch_types {
+        clone_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #patch_type: ::core::clone::Clone});
+    }
+    let (clone_impl_generics, _, clone_where_clause) = clone_generics.split_for_impl();
+
+    let clone_impl = if source.clone_forwarded {
+        TokenStream2::new()
+    } else {
+        quote!{
+            #[allow(deprecated)]
+            impl #clone_impl_generics ::core::clone::Clone for #patch_name #ty_generics #clone_where_clause {
+                fn clone(&self) -> Self {
+                    #patch_name {
+                        #(#cfgs #patch_fields: ::core::clone::Clone::clone(&self.#patch_fields)),*
+                    }
+                }
+            }
+        }
+    };
+
+    // Applying a borrowed patch only clones the fields that are not empty, and applies the result as usual.
+    let mut ref_generics = generics.clone();
+    ref_generics.params.insert(0, parse_quote!{'__ref});
+    ref_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #target: #krate::Patchable<#patch_name #ty_generics>});
+    if let Some(clone_where) = &clone_generics.where_clause {
+        ref_generics.make_where_clause().predicates.extend(clone_where.predicates.iter().cloned());
+    }
+    if let Some(patch_where) = &patch_generics.where_clause {
+        ref_generics.make_where_clause().predicates.extend(patch_where.predicates.iter().cloned());
+    }
+    let (ref_impl_generics, _, ref_where_clause) = ref_generics.split_for_impl();
+    let ref_values = fields.iter().zip(&empty_values).map(|(field, empty_value)| {
+        let patch_field = &field.patch_field;
+        let is_empty = field_is_empty(krate, field, quote!{patch});
+        quote!{
+            if #is_empty {
+                #empty_value
+            } else {
+                ::core::clone::Clone::clone(&patch.#patch_field)
+            }
+        }
+    });
+
+    let ref_impl = quote!{
+        #[allow(deprecated)]
+        impl #ref_impl_generics #krate::Patchable<&'__ref #patch_name #ty_generics> for #target #ref_where_clause {
+            fn apply_patch(&mut self, patch: &'__ref #patch_name #ty_generics) {
+                let patch = #patch_name {
+                    #(#cfgs #patch_fields: #ref_values),*
+                };
+                #krate::Patchable::apply_patch(self, patch);
+            }
+        }
+    };
+
+    let mut from_impls = Vec::new();
+    for from in source.from {
+        let values = fields.iter().map(|field| {
+            let patch_field = &field.patch_field;
+            if field.replaces {
+                quote!{::core::option::Option::Some(::core::convert::Into::into(from.#patch_field))}
+            } else {
+                quote!{::core::convert::Into::into(from.#patch_field)}
+            }
+        });
+        from_impls.push(quote!{
+            #[allow(deprecated)]
+            impl #struct_impl_generics ::core::convert::From<#from> for #patch_name #ty_generics #struct_where_clause {
+                fn from(from: #from) -> Self {
+                    #patch_name {
+                        #(#cfgs #patch_fields: #values),*
+                    }
+                }
+            }
+        });
+    }
+
+    let (patchable_impl, try_patchable_impl, ref_impl) = if source.no_impl {
+        (TokenStream2::new(), TokenStream2::new(), TokenStream2::new())
+    } else {
+        (patchable_impl, try_patchable_impl, ref_impl)
     };
 
-    let output = quote!{
+    quote!{
         #patch_struct
         #patchable_impl
+        #try_patchable_impl
+        #ref_impl
+        #patch_impl
+        #clone_impl
+        #inherent_impl
+        #example_impl
+        #merge_impl
+        #collect_impl
+        #visit_impl
+        #(#from_impls)*
+    }
+}
+
+/// Generates the `{Name}Change` enum of `#[patch(changes)]`, with one variant per field holding its old and new
+/// values, and `apply_patch_changes`, which records them for the fields whose patch was not empty.
+fn expand_changes(source: &Source, input_name: &Ident, patch_name: &Ident, fields: &[&PatchField]) -> TokenStream2 {
+    let krate = source.krate;
+    let input_vis = source.vis;
+    let generics = source.generics;
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let struct_generics = patch_struct_generics(generics, fields);
+    let (_, struct_ty_generics, _) = struct_generics.split_for_impl();
+    let change_name = format_ident!("{}Change", input_name);
+
+    // The enum holds the values behind `&mut T` fields, so it leaves out the lifetimes that only those references use.
+    let enum_generics = used_generics(generics, fields.iter().map(|field| field.ty.to_token_stream()));
+    let (_, enum_ty_generics, enum_where_clause) = enum_generics.split_for_impl();
+
+    let mut change_generics = generics.clone();
+    change_generics.make_where_clause().predicates.push(parse_quote!{Self: #krate::Patchable<#patch_name #struct_ty_generics>});
+    let groups = FieldGroups::new(fields);
+    let group_checks = groups.checks(krate, quote!{patch});
+    let mut variants = Vec::new();
+    let mut saves = Vec::new();
+    let mut records = Vec::new();
+    for field in fields {
+        let ty = &field.ty;
+        let name = &field.name;
+        let patch_type = &field.patch_type;
+        let cfg = field.cfg();
+        let variant = format_ident!("{}", pascal_case(&name.unraw().to_string()), span = name.span());
+        let old = format_ident!("__patch_old_{}", name.unraw());
+        let is_empty = groups.is_empty(krate, field, quote!{patch});
+        let value = if field.reborrow { quote!{*self.#name} } else { quote!{self.#name} };
+        change_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #ty: ::core::clone::Clone});
+        if field.empty_if.is_none() {
+            change_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #patch_type: #krate::Patch});
+        }
+        variants.push(quote!{#cfg #variant { old: #ty, new: #ty }});
+        saves.push(quote!{
+            #cfg
+            let #old = if #is_empty {
+                ::core::option::Option::None
+            } else {
+                ::core::option::Option::Some(::core::clone::Clone::clone(&#value))
+            };
+        });
+        records.push(quote!{
+            #cfg
+            if let ::core::option::Option::Some(old) = #old {
+                changes.push(#change_name::#variant { old, new: ::core::clone::Clone::clone(&#value) });
+            }
+        });
+    }
+    let (impl_generics, _, change_where_clause) = change_generics.split_for_impl();
+
+    let enum_params = &enum_generics.params;
+    quote!{
+        #[derive(::core::fmt::Debug, ::core::clone::Clone, ::core::cmp::PartialEq)]
+        #input_vis enum #change_name <#enum_params> #enum_where_clause {
+            #(#variants),*
+        }
+
+        impl #impl_generics #input_name #ty_generics #change_where_clause {
+            /// Applies a patch and returns the old and new values of the fields whose patch was not empty.
+            pub fn apply_patch_changes(&mut self, patch: #patch_name #struct_ty_generics) -> ::std::vec::Vec<#change_name #enum_ty_generics> {
+                let mut changes = ::std::vec::Vec::new();
+                #group_checks
+                #(#saves)*
+                #krate::Patchable::apply_patch(self, patch);
+                #(#records)*
+                changes
+            }
+        }
+    }
+}
+
+/// Returns the generics of the patch struct: those of the source struct without the lifetimes that only
+/// reborrowed fields use, since the patch struct does not hold the references.
+fn patch_struct_generics(generics: &Generics, fields: &[&PatchField]) -> Generics {
+    used_generics(generics, fields.iter().map(|field| field.patch_type.to_token_stream()))
+}
+
+/// Returns `generics` without the lifetime parameters that none of `types` use, and the where predicates on them.
+fn used_generics(generics: &Generics, types: impl Iterator<Item = TokenStream2>) -> Generics {
+    let mut used = Vec::new();
+    for ty in types {
+        lifetimes_in(ty, &mut used);
+    }
+    let mut filtered = generics.clone();
+    filtered.params = generics.params.iter()
+        .filter(|param| match param {
+            GenericParam::Lifetime(param) => used.contains(&param.lifetime.ident.to_string()),
+            _ => true,
+        })
+        .cloned()
+        .collect();
+    if let Some(where_clause) = &mut filtered.where_clause {
+        where_clause.predicates = where_clause.predicates.iter()
+            .filter(|predicate| {
+                let mut lifetimes = Vec::new();
+                lifetimes_in(predicate.to_token_stream(), &mut lifetimes);
+                lifetimes.iter().all(|lifetime| used.contains(lifetime) || lifetime == "static")
+            })
+            .cloned()
+            .collect();
+    }
+    filtered
+}
+
+/// Adds the names of the lifetimes in `tokens` to `lifetimes`, without the leading `'`.
+fn lifetimes_in(tokens: TokenStream2, lifetimes: &mut Vec<String>) {
+    let mut after_quote = false;
+    for tree in tokens {
+        match tree {
+            proc_macro2::TokenTree::Group(group) => lifetimes_in(group.stream(), lifetimes),
+            proc_macro2::TokenTree::Ident(ident) if after_quote => lifetimes.push(ident.to_string()),
+            proc_macro2::TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+                after_quote = true;
+                continue;
+            },
+            _ => {},
+        }
+        after_quote = false;
+    }
+}
+
+/// Converts a `snake_case` field name to `PascalCase` for a variant name.
+fn pascal_case(name: &str) -> String {
+    name.split('_').filter(|part| !part.is_empty()).map(|part| {
+        let mut chars = part.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }).collect()
+}
+
+/// Sorts fields into the order they are applied in, each after the fields named in its `after` that are among
+/// `fields`. Otherwise, fields named in `order` go first and the rest keep declaration order.
+/// Returns the fields on a cycle of `after` dependencies if there is one, each applied after the next.
+fn apply_order<'f>(fields: &[&'f PatchField], order: &[Ident]) -> std::result::Result<Vec<&'f PatchField>, Vec<&'f PatchField>> {
+    // The sort is stable, and each step takes the first field whose dependencies are all applied.
+    let mut pending = fields.to_vec();
+    pending.sort_by_key(|field| order.iter().position(|ordered| *ordered == field.name).unwrap_or(order.len()));
+    let mut sorted = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|field| {
+            field.after.iter().all(|after| !pending.iter().any(|other| other.name == *after))
+        });
+        match ready {
+            Some(index) => sorted.push(pending.remove(index)),
+            None => return Err(dependency_cycle(&pending)),
+        }
+    }
+    Ok(sorted)
+}
+
+/// Follows the `after` dependencies among `pending`, where every field has one, until a field repeats, and returns
+/// the fields from its first visit on.
+fn dependency_cycle<'f>(pending: &[&'f PatchField]) -> Vec<&'f PatchField> {
+    let mut path = vec![pending[0]];
+    loop {
+        let last = path[path.len() - 1];
+        let next = pending.iter()
+            .find(|other| last.after.contains(&other.name))
+            .copied()
+            .expect("every pending field has a pending dependency");
+        if let Some(start) = path.iter().position(|field| field.name == next.name) {
+            return path.split_off(start);
+        }
+        path.push(next);
+    }
+}
+
+/// Returns the type of the patch field for a field of type `ty` with the given `#[patch(...)]` kind.
+fn field_patch_type(krate: &Path, ty: &Type, kind: Option<FieldKind>) -> Result<TokenStream2> {
+    Ok(match kind {
+        None | Some(FieldKind::Replace) => quote!{::core::option::Option<#ty>},
+        Some(FieldKind::Type(patch_type)) => quote!{#patch_type},
+        Some(FieldKind::Nested) => quote!{<#ty as #krate::HasPatch>::Patch},
+        Some(FieldKind::FlattenOption) => match generic_argument(ty, "Option") {
+            Some(inner) => quote!{::core::option::Option<#inner>},
+            None => return Err(Error::new_spanned(ty, "`flatten_option` requires an `Option<T>` field")),
+        },
+        Some(FieldKind::NestedOption { init_default }) => match generic_argument(ty, "Option") {
+            Some(inner) if init_default => quote!{#krate::InitPatch<<#inner as #krate::HasPatch>::Patch>},
+            Some(inner) => quote!{#krate::InnerPatch<<#inner as #krate::HasPatch>::Patch>},
+            None => return Err(Error::new_spanned(ty, "`nested_option` requires an `Option<T>` field")),
+        },
+        Some(FieldKind::ListOrClear) => match list_element(ty) {
+            Some(element) => quote!{#krate::ListPatch<#element>},
+            None => return Err(Error::new_spanned(ty, "`list_or_clear` requires a `Vec<T>` or `Option<Vec<T>>` field")),
+        },
+    })
+}
+
+/// Expands `#[patch(transparent)]`, which uses the patch type of the only field as the patch of the struct.
+fn expand_transparent(krate: &Path, input: &DeriveInput, container: &ContainerAttrs, has_patch: bool) -> Result<TokenStream2> {
+    if container.name.is_some() || container.forward_attrs || container.private_fields || !container.profiles.is_empty()
+        || !container.order.is_empty() || container.target.is_some() || !container.from.is_empty()
+        || container.on_apply.is_some() || container.post_apply.is_some() || container.validate_all.is_some()
+        || container.no_impl || container.changes || container.examples.is_some() || container.serde
+        || container.deny_unknown_fields.is_some()
+        || container.untagged.is_some() || container.schemars || container.rename_all.is_some()
+    {
+        return Err(Error::new_spanned(&container.transparent, "`transparent` generates no patch struct and only takes `nested`, `bound` and `crate`"));
+    }
+    let fields = match &input.data {
+        Data::Struct(struct_data) if struct_data.fields.len() == 1 => &struct_data.fields,
+        _ => return Err(Error::new_spanned(&input.ident, "`transparent` requires a struct with exactly one field")),
+    };
+    let field = fields.iter().next().unwrap();
+    let member = match &field.ident {
+        Some(ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(Index::from(0)),
+    };
+    let field_attrs = parse_field_attrs(&field.attrs)?;
+    let kind = if container.nested {
+        if field_attrs.kind.is_some() {
+            return Err(Error::new_spanned(field, "the patch kind is already set by `nested` on the struct"));
+        }
+        Some(FieldKind::Nested)
+    } else {
+        field_attrs.kind
+    };
+    let forwards_try = matches!(kind, Some(FieldKind::Nested));
+    let patch_type = field_patch_type(krate, &field.ty, kind)?;
+    let ty = &field.ty;
+
+    let mut generics = input.generics.clone();
+    if !container.bounds.is_empty() {
+        generics.make_where_clause().predicates.extend(container.bounds.iter().cloned());
+    }
+    let input_name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    // A nested patch can be rejected by the field's own `TryPatchable`, other patches, such as the `Option` of the
+    // default kind, are applied as they are and always accepted.
+    let mut try_generics = generics.clone();
+    let try_apply = if forwards_try {
+        try_generics.make_where_clause().predicates.push(parse_quote!{for<'__patch> #ty: #krate::TryPatchable<#patch_type>});
+        quote!{#krate::TryPatchable::try_apply_patch(&mut self.#member, patch)}
+    } else {
+        quote!{
+            #krate::Patchable::apply_patch(&mut self.#member, patch);
+            ::core::result::Result::Ok(())
+        }
+    };
+    let (_, _, try_where_clause) = try_generics.split_for_impl();
+
+    let mut output = quote!{
+        impl #impl_generics #krate::Patchable<#patch_type> for #input_name #ty_generics #where_clause {
+            #[inline]
+            fn apply_patch(&mut self, patch: #patch_type) {
+                #krate::Patchable::apply_patch(&mut self.#member, patch)
+            }
+        }
+
+        impl #impl_generics #krate::TryPatchable<#patch_type> for #input_name #ty_generics #try_where_clause {
+            #[inline]
+            fn try_apply_patch(&mut self, patch: #patch_type) -> ::core::result::Result<(), #krate::PatchError> {
+                #try_apply
+            }
+        }
+    };
+    if has_patch {
+        output.extend(quote!{
+            impl #impl_generics #krate::HasPatch for #input_name #ty_generics #where_clause {
+                type Patch = #patch_type;
+            }
+        });
+    }
+    Ok(output)
+}
+
+/// Generates a patch enum with one variant per variant of the source enum, each holding the patch type of the
+/// variant's field, and `Patchable` impls applying it to the value of the same variant.
+fn expand_enum(source: &Source, input_name: &Ident, container: &ContainerAttrs, enum_data: DataEnum, has_patch: bool) -> Result<TokenStream2> {
+    if !container.profiles.is_empty() || !container.order.is_empty() || container.target.is_some()
+        || !container.from.is_empty() || container.on_apply.is_some() || container.post_apply.is_some() || container.no_impl
+        || container.changes || container.validate_all.is_some() || container.examples.is_some()
+    {
+        return Err(Error::new_spanned(input_name, "`profile`, `order`, `target`, `from`, `on_apply`, `post_apply`, `validate_all`, `no_impl`, `changes` and `examples` are not supported on enums"));
+    }
+    if enum_data.variants.is_empty() {
+        return Err(Error::new_spanned(input_name, "cannot derive `Patchable` for an enum without variants"));
+    }
+
+    let krate = source.krate;
+    let input_vis = source.vis;
+    let target = &source.target;
+    let struct_attrs = source.struct_attrs;
+    let generics = source.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let patch_name = match &container.name {
+        Some(name) => name.clone(),
+        None => format_ident!("{}Patch", input_name),
+    };
+
+    let mut patch_variants = Vec::new();
+    let mut apply_arms = Vec::new();
+    let mut try_apply_arms = Vec::new();
+    let mut source_names = Vec::new();
+    let mut patch_names = Vec::new();
+    for variant in enum_data.variants {
+        let field_attrs = parse_field_attrs(&variant.attrs)?;
+        if field_attrs.rename.is_some() || !field_attrs.aliases.is_empty() || !field_attrs.validators.is_empty()
+            || field_attrs.empty_if.is_some() || field_attrs.feature.is_some() || field_attrs.inspect.is_some()
+            || field_attrs.min_version.is_some() || field_attrs.map.is_some() || field_attrs.input.is_some()
+            || field_attrs.group.is_some() || field_attrs.key.is_some() || !field_attrs.after.is_empty()
+        {
+            return Err(Error::new_spanned(&variant.ident, "`rename`, `alias`, `validate`, `empty_if`, `feature`, `inspect`, `min_version`, `map`, `input`, `group`, `key` and `after` are not supported on enum variants"));
+        }
+        let attrs = field_attrs.deprecated;
+        let name = variant.ident;
+        let name_str = LitStr::new(&name.unraw().to_string(), name.span());
+        match variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                if matches!(field_attrs.kind, Some(FieldKind::FlattenOption)) {
+                    return Err(Error::new_spanned(&name, "`flatten_option` is not supported on enum variants"));
+                }
+                let patch_type = field_patch_type(krate, ty, field_attrs.kind)?;
+                patch_variants.push(quote!{#attrs #name(#patch_type)});
+                apply_arms.push(quote!{
+                    (#input_name::#name(value), #patch_name::#name(patch)) => #krate::Patchable::apply_patch(value, patch),
+                });
+                try_apply_arms.push(quote!{
+                    (#input_name::#name(value), #patch_name::#name(patch)) => {
+                        #krate::Patchable::apply_patch(value, patch);
+                        ::core::result::Result::Ok(())
+                    },
+                });
+                source_names.push(quote!{#input_name::#name(..) => #name_str});
+                patch_names.push(quote!{#patch_name::#name(..) => #name_str});
+            },
+            Fields::Unit => {
+                if field_attrs.kind.is_some() {
+                    return Err(Error::new_spanned(&name, "a unit variant has no field to patch"));
+                }
+                patch_variants.push(quote!{#attrs #name});
+                apply_arms.push(quote!{(#input_name::#name, #patch_name::#name) => {},});
+                try_apply_arms.push(quote!{(#input_name::#name, #patch_name::#name) => ::core::result::Result::Ok(()),});
+                source_names.push(quote!{#input_name::#name => #name_str});
+                patch_names.push(quote!{#patch_name::#name => #name_str});
+            },
+            _ => return Err(Error::new_spanned(&name, "only unit variants and variants with a single unnamed field can be patched")),
+        }
+    }
+
+    let has_patch_impl = if has_patch {
+        quote!{
+            impl #impl_generics #krate::HasPatch for #target #where_clause {
+                type Patch = #patch_name #ty_generics;
+            }
+        }
+    } else {
+        TokenStream2::new()
+    };
+
+    Ok(quote!{
+        #(#struct_attrs)*
+        #input_vis enum #patch_name #generics #where_clause {
+            #(#patch_variants),*
+        }
+
+        #[allow(deprecated)]
+        impl #impl_generics #krate::Patchable<#patch_name #ty_generics> for #target #where_clause {
+            fn apply_patch(&mut self, patch: #patch_name #ty_generics) {
+                #[allow(unreachable_patterns)]
+                match (self, patch) {
+                    #(#apply_arms)*
+                    _ => {},
+                }
+            }
+        }
+
+        #[allow(deprecated)]
+        impl #impl_generics #krate::TryPatchable<#patch_name #ty_generics> for #target #where_clause {
+            fn try_apply_patch(&mut self, patch: #patch_name #ty_generics) -> ::core::result::Result<(), #krate::PatchError> {
+                #[allow(unreachable_patterns)]
+                match (self, patch) {
+                    #(#try_apply_arms)*
+                    (value, patch) => ::core::result::Result::Err(#krate::PatchError::VariantMismatch {
+                        expected: match patch {
+                            #(#patch_names),*
+                        },
+                        found: match value {
+                            #(#source_names),*
+                        },
+                    }),
+                }
+            }
+        }
+
+        #has_patch_impl
+    })
+}
+
+/// Fields of a patch struct sorted into their groups, in order of the first field, for the checks of whether each
+/// group is applied.
+struct FieldGroups<'f> {
+    groups: Vec<(&'f LitStr, Vec<&'f PatchField>)>,
+    /// Variables set by `checks`, one for each group.
+    vars: Vec<Ident>,
+}
+
+impl<'f> FieldGroups<'f> {
+    fn new(fields: &[&'f PatchField]) -> Self {
+        let mut groups: Vec<(&LitStr, Vec<&PatchField>)> = Vec::new();
+        for field in fields {
+            if let Some(group) = &field.group {
+                match groups.iter_mut().find(|(name, _)| name.value() == group.value()) {
+                    Some((_, members)) => members.push(field),
+                    None => groups.push((group, vec![field])),
+                }
+            }
+        }
+        let vars = (0..groups.len()).map(|index| format_ident!("__patch_group_{}", index)).collect();
+        FieldGroups { groups, vars }
+    }
+
+    /// Returns statements setting the variable of each group to whether the group of `patch` is applied.
+    /// A group is applied if all or none of its fields are set, a partly set group is left out.
+    /// Fields left out by `feature` are not counted, as they do not exist in the build.
+    fn checks(&self, krate: &Path, patch: TokenStream2) -> TokenStream2 {
+        let checks = self.groups.iter().zip(&self.vars).map(|((_, members), var)| {
+            let counts = members.iter().map(|field| {
+                let cfg = field.cfg();
+                let is_empty = field_is_empty(krate, field, patch.clone());
+                quote!{
+                    #cfg
+                    {
+                        total += 1;
+                        set += !#is_empty as usize;
+                    }
+                }
+            });
+            quote!{
+                let #var = {
+                    #[allow(unused_mut)]
+                    let mut total = 0usize;
+                    #[allow(unused_mut)]
+                    let mut set = 0usize;
+                    #(#counts)*
+                    set == 0 || set == total
+                };
+            }
+        });
+        quote!{#(#checks)*}
+    }
+
+    /// Returns the variable of the group of `field`, if it is in one.
+    fn var(&self, field: &PatchField) -> Option<&Ident> {
+        let group = field.group.as_ref()?;
+        let index = self.groups.iter().position(|(name, _)| name.value() == group.value())?;
+        Some(&self.vars[index])
+    }
+
+    /// Like `field_is_empty`, but also counts a field as empty when its group is left out, after `checks`.
+    fn is_empty(&self, krate: &Path, field: &PatchField, patch: TokenStream2) -> TokenStream2 {
+        let is_empty = field_is_empty(krate, field, patch);
+        match self.var(field) {
+            Some(var) => quote!{(#is_empty || !#var)},
+            None => is_empty,
+        }
+    }
+}
+
+/// Returns an expression checking whether `field` of the patch struct `patch` is empty.
+/// A field that is left out by its `feature` is always empty.
+fn field_is_empty(krate: &Path, field: &PatchField, patch: TokenStream2) -> TokenStream2 {
+    let patch_field = &field.patch_field;
+    let is_empty = match &field.empty_if {
+        Some(empty_if) => quote!{#empty_if(&#patch.#patch_field)},
+        None => quote!{#krate::Patch::is_empty(&#patch.#patch_field)},
     };
+    match &field.feature {
+        Some(feature) => quote!{{
+            #[cfg(feature = #feature)]
+            let is_empty = #is_empty;
+            #[cfg(not(feature = #feature))]
+            let is_empty = true;
+            is_empty
+        }},
+        None => is_empty,
+    }
+}
+
+/// Options set by `#[patch(...)]` attributes on the source struct.
+#[derive(Default)]
+struct ContainerAttrs {
+    name: Option<Ident>,
+    /// Set by the bare `#[patch(Name)]`, which is deprecated in favor of `rename_type`.
+    positional_name: Option<Ident>,
+    forward_attrs: bool,
+    private_fields: bool,
+    profiles: Vec<Profile>,
+    order: Vec<Ident>,
+    bounds: Vec<WherePredicate>,
+    krate: Option<Path>,
+    target: Option<Type>,
+    from: Vec<Type>,
+    on_apply: Option<Path>,
+    post_apply: Option<Path>,
+    validate_all: Option<Path>,
+    no_impl: bool,
+    changes: bool,
+    examples: Option<Path>,
+    serde: bool,
+    deny_unknown_fields: Option<Path>,
+    /// Set by `#[patch(untagged)]`, which is only valid on enums.
+    untagged: Option<Path>,
+    schemars: bool,
+    rename_all: Option<LitStr>,
+    /// Set by `#[patch(transparent)]`, which forwards the patch of a single-field struct to its field.
+    transparent: Option<Path>,
+    /// Set by `#[patch(nested)]`, which is only valid with `transparent`.
+    nested: bool,
+}
+
+/// Rules accepted by serde's `rename_all`.
+const RENAME_RULES: &[&str] = &[
+    "lowercase",
+    "UPPERCASE",
+    "PascalCase",
+    "camelCase",
+    "snake_case",
+    "SCREAMING_SNAKE_CASE",
+    "kebab-case",
+    "SCREAMING-KEBAB-CASE",
+];
 
-    TokenStream::from(output)
+/// A `#[patch(profile(Name, fields(...)))]` subset of fields, generating its own `NamePatch` struct.
+struct Profile {
+    name: Ident,
+    fields: Vec<Ident>,
 }
 
-fn parse_attrs_ident(attrs: Vec<Attribute>) -> Option<Ident> {
+fn parse_container_attrs(attrs: &[Attribute]) -> Result<ContainerAttrs> {
+    let mut container = ContainerAttrs::default();
     for attr in attrs {
-        if let Some(ident) = parse_attr_ident(attr) {
-            return Some(ident);
+        if !attr.path.is_ident("patch") {
+            continue;
+        }
+        if let Meta::List(meta_list) = attr.parse_meta()? {
+            for nested in meta_list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("forward_attrs") => {
+                        container.forward_attrs = true;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("private_fields") => {
+                        container.private_fields = true;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("serde") => {
+                        container.serde = true;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("untagged") => {
+                        container.untagged = Some(path);
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("deny_unknown_fields") => {
+                        container.deny_unknown_fields = Some(path);
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("schemars") => {
+                        container.schemars = true;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("no_impl") => {
+                        container.no_impl = true;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("changes") => {
+                        container.changes = true;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("examples") => {
+                        if !cfg!(feature = "test-util") {
+                            return Err(Error::new_spanned(path, "`examples` requires the `test-util` feature of `patchable`"));
+                        }
+                        container.examples = Some(path);
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("transparent") => {
+                        container.transparent = Some(path);
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("nested") => {
+                        container.nested = true;
+                    },
+                    // Other bare words are only names if they look like type names, so a misspelled flag is an error.
+                    NestedMeta::Meta(Meta::Path(path)) if path.get_ident().map(is_type_name).unwrap_or(false) => {
+                        let name = path.get_ident().unwrap().clone();
+                        set_patch_name(&mut container, name.clone())?;
+                        container.positional_name = Some(name);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(name), .. })) if path.is_ident("rename_type") => {
+                        set_patch_name(&mut container, name.parse()?)?;
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(rename_all), .. })) if path.is_ident("rename_all") => {
+                        if !RENAME_RULES.contains(&rename_all.value().as_str()) {
+                            let message = format!("unknown rename rule, expected one of {}", RENAME_RULES.join(", "));
+                            return Err(Error::new_spanned(rename_all, message));
+                        }
+                        container.rename_all = Some(rename_all);
+                    },
+                    NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("profile") => {
+                        container.profiles.push(parse_profile(list)?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(bound), .. })) if path.is_ident("bound") => {
+                        let predicates = bound.parse_with(punctuated::Punctuated::<WherePredicate, Token![,]>::parse_terminated)?;
+                        container.bounds.extend(predicates);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(krate), .. })) if path.is_ident("crate") => {
+                        container.krate = Some(krate.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(target), .. })) if path.is_ident("target") => {
+                        container.target = Some(target.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(from), .. })) if path.is_ident("from") => {
+                        container.from.push(from.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(on_apply), .. })) if path.is_ident("on_apply") => {
+                        container.on_apply = Some(on_apply.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(post_apply), .. })) if path.is_ident("post_apply") => {
+                        container.post_apply = Some(post_apply.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(validate_all), .. })) if path.is_ident("validate_all") => {
+                        container.validate_all = Some(validate_all.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("order") => {
+                        container.order.extend(parse_field_names(&list)?);
+                    },
+                    other => return Err(Error::new_spanned(other, "unknown patch attribute")),
+                }
+            }
         }
     }
-    None
+    Ok(container)
+}
+
+/// Returns whether `ident` is written in `UpperCamelCase`, like a type name.
+fn is_type_name(ident: &Ident) -> bool {
+    let name = ident.unraw().to_string();
+    name.starts_with(|c: char| c.is_ascii_uppercase()) && !name.contains('_')
+}
+
+/// Sets the name of the patch struct, from either `#[patch(Name)]` or `#[patch(rename_type = "Name")]`.
+fn set_patch_name(container: &mut ContainerAttrs, name: Ident) -> Result<()> {
+    if let Some(existing) = &container.name {
+        if *existing != name {
+            return Err(Error::new_spanned(name, format!("the patch struct is already named `{}`", existing)));
+        }
+    }
+    container.name = Some(name);
+    Ok(())
+}
+
+fn parse_profile(list: MetaList) -> Result<Profile> {
+    let mut name = None;
+    let mut fields = Vec::new();
+    for nested in &list.nested {
+        match nested {
+            NestedMeta::Meta(Meta::Path(path)) if name.is_none() && path.get_ident().is_some() => {
+                name = path.get_ident().cloned();
+            },
+            NestedMeta::Meta(Meta::List(field_list)) if field_list.path.is_ident("fields") => {
+                fields.extend(parse_field_names(field_list)?);
+            },
+            other => return Err(Error::new_spanned(other, "unknown profile attribute")),
+        }
+    }
+    match name {
+        Some(name) => Ok(Profile { name, fields }),
+        None => Err(Error::new_spanned(list, "profile requires a name")),
+    }
+}
+
+fn parse_field_names(list: &MetaList) -> Result<Vec<Ident>> {
+    let mut names = Vec::new();
+    for nested in &list.nested {
+        match nested {
+            NestedMeta::Meta(Meta::Path(path)) if path.get_ident().is_some() => {
+                names.push(path.get_ident().cloned().unwrap());
+            },
+            other => return Err(Error::new_spanned(other, "expected a field name")),
+        }
+    }
+    Ok(names)
+}
+
+/// Options set by `#[patch(...)]` attributes on a field.
+#[derive(Default)]
+struct FieldAttrs {
+    kind: Option<FieldKind>,
+    deprecated: Option<TokenStream2>,
+    validators: Vec<Validator>,
+    empty_if: Option<Path>,
+    rename: Option<LitStr>,
+    aliases: Vec<LitStr>,
+    feature: Option<LitStr>,
+    inspect: Option<Path>,
+    min_version: Option<LitInt>,
+    map: Option<Path>,
+    input: Option<Type>,
+    group: Option<LitStr>,
+    key: Option<Path>,
+    init_default: Option<Path>,
+    after: Vec<Ident>,
 }
 
-fn parse_attr_ident(attr: Attribute) -> Option<Ident> {
-    if let Ok(Meta::List(meta_list)) = attr.parse_meta() {
-        if meta_list.path.is_ident("patch") {
-            if let Some(NestedMeta::Meta(Meta::Path(path))) = meta_list.nested.first() {
-                return path.get_ident().cloned();
+fn parse_field_attrs(attrs: &[Attribute]) -> Result<FieldAttrs> {
+    let mut field = FieldAttrs::default();
+    for attr in attrs {
+        if !attr.path.is_ident("patch") {
+            continue;
+        }
+        if attr.tokens.is_empty() {
+            continue;
+        }
+        for arg in attr.parse_args_with(punctuated::Punctuated::<FieldArg, Token![,]>::parse_terminated)? {
+            match arg {
+                FieldArg::Validate(validator) => field.validators.push(validator),
+                FieldArg::Type(ty) => field.set_kind(&ty, FieldKind::Type(Box::new(ty.clone())))?,
+                FieldArg::Meta(nested) => match nested {
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("nested") => {
+                        field.set_kind(&path, FieldKind::Nested)?;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("replace") => {
+                        field.set_kind(&path, FieldKind::Replace)?;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("flatten_option") => {
+                        field.set_kind(&path, FieldKind::FlattenOption)?;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("list_or_clear") => {
+                        field.set_kind(&path, FieldKind::ListOrClear)?;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("nested_option") => {
+                        field.set_kind(&path, FieldKind::NestedOption { init_default: false })?;
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("init_default") => {
+                        field.init_default = Some(path);
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("deprecated") => {
+                        field.deprecated = Some(quote!{#[deprecated]});
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("key") => {
+                        field.key = Some(path);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(note), .. })) if path.is_ident("deprecated") => {
+                        field.deprecated = Some(quote!{#[deprecated(note = #note)]});
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(rename), .. })) if path.is_ident("rename") => {
+                        field.rename = Some(rename);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(alias), .. })) if path.is_ident("alias") => {
+                        field.aliases.push(alias);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(feature), .. })) if path.is_ident("feature") => {
+                        field.feature = Some(feature);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(ty), .. })) if path.is_ident("ty") => {
+                        field.set_kind(&path, FieldKind::Type(Box::new(ty.parse()?)))?;
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(map), .. })) if path.is_ident("map") => {
+                        field.map = Some(map.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(input), .. })) if path.is_ident("input") => {
+                        field.input = Some(input.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(inspect), .. })) if path.is_ident("inspect") => {
+                        field.inspect = Some(inspect.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Int(min_version), .. })) if path.is_ident("min_version") => {
+                        min_version.base10_parse::<u32>()?;
+                        field.min_version = Some(LitInt::new(&format!("{}u32", min_version.base10_digits()), min_version.span()));
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(after), .. })) if path.is_ident("after") => {
+                        field.after.push(after.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(group), .. })) if path.is_ident("group") => {
+                        field.group = Some(group);
+                    },
+                    NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit: Lit::Str(empty_if), .. })) if path.is_ident("empty_if") => {
+                        field.empty_if = Some(empty_if.parse()?);
+                    },
+                    NestedMeta::Meta(Meta::Path(path)) => {
+                        field.set_kind(&path, FieldKind::Type(Box::new(Type::Path(TypePath { qself: None, path: path.clone() }))))?;
+                    },
+                    other => return Err(Error::new_spanned(other, "unknown patch attribute")),
+                },
+            }
+        }
+    }
+    if let Some(path) = &field.init_default {
+        match &mut field.kind {
+            Some(FieldKind::NestedOption { init_default }) => *init_default = true,
+            _ => return Err(Error::new_spanned(path, "`init_default` requires `nested_option`")),
+        }
+    }
+    Ok(field)
+}
+
+/// How a field is patched, which decides the type of its patch field.
+enum FieldKind {
+    /// `#[patch(replace)]`, the same as the default `Option` of the field type.
+    Replace,
+    /// `#[patch(PatchType)]`, which can be any type such as `PatchType<T>`.
+    Type(Box<Type>),
+    /// `#[patch(nested)]`, using the field type's `HasPatch::Patch`.
+    Nested,
+    /// `#[patch(list_or_clear)]`, using `ListPatch` of the list's element type.
+    ListOrClear,
+    /// `#[patch(flatten_option)]`, patching an `Option<T>` field with an `Option<T>` that sets it to `Some`.
+    FlattenOption,
+    /// `#[patch(nested_option)]`, patching the value in an `Option<T>` field with `T`'s `HasPatch::Patch`,
+    /// starting from `T::default()` when the field is `None` if `init_default` is set.
+    NestedOption { init_default: bool },
+}
+
+impl FieldAttrs {
+    fn set_kind(&mut self, tokens: &dyn ToTokens, kind: FieldKind) -> Result<()> {
+        if self.kind.is_some() {
+            return Err(Error::new_spanned(tokens, "a field can only have one patch type"));
+        }
+        self.kind = Some(kind);
+        Ok(())
+    }
+}
+
+/// Returns `T` for a field of type `Vec<T>` or `Option<Vec<T>>`.
+fn list_element(ty: &Type) -> Option<&Type> {
+    let inner = generic_argument(ty, "Option").unwrap_or(ty);
+    generic_argument(inner, "Vec")
+}
+
+/// Returns `T` if `ty` is a path to `name<T>`.
+fn generic_argument<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    if let Type::Path(TypePath { qself: None, path }) = ty {
+        let segment = path.segments.last()?;
+        if segment.ident == name {
+            if let PathArguments::AngleBracketed(arguments) = &segment.arguments {
+                if let Some(GenericArgument::Type(argument)) = arguments.args.first() {
+                    return Some(argument);
+                }
             }
         }
     }
     None
 }
+
+/// One item of a field's `#[patch(...)]` attribute.
+///
+/// Most items are ordinary meta items, but `validate(...)` holds a closure, which `parse_meta` cannot parse.
+enum FieldArg {
+    Validate(Validator),
+    Meta(NestedMeta),
+    /// A patch type that is not a plain path, such as `PatchType<T>`.
+    Type(Type),
+}
+
+/// A `#[patch(validate(|value| ..., "message"))]` check on the incoming value of a field.
+struct Validator {
+    check: Expr,
+    message: LitStr,
+}
+
+impl parse::Parse for FieldArg {
+    fn parse(input: parse::ParseStream) -> Result<Self> {
+        if input.peek(Ident) && input.peek2(token::Paren) && input.fork().parse::<Ident>()? == "validate" {
+            input.parse::<Ident>()?;
+            let content;
+            parenthesized!(content in input);
+            let check = content.parse()?;
+            content.parse::<Token![,]>()?;
+            let message = content.parse()?;
+            content.parse::<Option<Token![,]>>()?;
+            return Ok(FieldArg::Validate(Validator { check, message }));
+        }
+        let fork = input.fork();
+        if fork.parse::<NestedMeta>().is_ok() && (fork.is_empty() || fork.peek(Token![,])) {
+            return input.parse().map(FieldArg::Meta);
+        }
+        input.parse().map(FieldArg::Type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expands the derive for `source`, with the tokens printed without spaces.
+    fn expand(source: &str) -> String {
+        let input: DeriveInput = parse_str(source).unwrap();
+        expand_derive(input).unwrap().to_string().replace(' ', "")
+    }
+
+    /// Expands the derive for `source`, and returns the error message and the source text it points at.
+    fn expand_error(source: &str) -> (String, String) {
+        let input: DeriveInput = parse_str(source).unwrap();
+        let err = expand_derive(input).expect_err("the derive should fail");
+        let (start, end) = (err.span().start(), err.span().end());
+        let lines: Vec<&str> = source.lines().collect();
+        let text = if start.line == end.line {
+            lines[start.line - 1][start.column..end.column].to_string()
+        } else {
+            let mut text = lines[start.line - 1][start.column..].to_string();
+            for line in &lines[start.line..end.line - 1] {
+                text.push('\n');
+                text.push_str(line);
+            }
+            text.push('\n');
+            text.push_str(&lines[end.line - 1][..end.column]);
+            text
+        };
+        (err.to_string(), text)
+    }
+
+    #[test]
+    fn duplicate_patch_field_from_rename() {
+        let (message, text) = expand_error(r#"
+            struct MyStruct {
+                title: String,
+                #[patch(rename = "title")]
+                name: String,
+            }
+        "#);
+        assert_eq!(message, "patch field `title` is already generated for field `title`");
+        assert_eq!(text, r#""title""#);
+    }
+
+    #[test]
+    fn duplicate_patch_field_from_raw_identifier() {
+        let (message, text) = expand_error(r#"
+            struct MyStruct {
+                #[patch(rename = "kind")]
+                name: String,
+                r#kind: String,
+            }
+        "#);
+        assert_eq!(message, "patch field `kind` is already generated for field `name`");
+        assert_eq!(text, "r#kind");
+    }
+
+    #[test]
+    fn after_cycle() {
+        let (message, text) = expand_error(r#"
+            struct Cycle {
+                #[patch(after = "b")]
+                a: u32,
+                #[patch(after = "c")]
+                b: u32,
+                #[patch(after = "b")]
+                c: u32,
+            }
+        "#);
+        assert_eq!(message, "the `after` dependencies of `b` form a cycle: `b` -> `c` -> `b`");
+        assert_eq!(text, "b");
+    }
+
+    #[test]
+    fn shared_reference_cannot_be_patched_through() {
+        let (message, text) = expand_error(r#"
+            struct Session<'a> {
+                #[patch(nested)]
+                config: &'a Config,
+            }
+        "#);
+        assert_eq!(message, "a shared reference cannot be patched through; use `&mut T`, or replace the whole reference without a patch type");
+        assert_eq!(text, "&'a Config");
+    }
+
+    #[test]
+    fn raw_field_names_are_reported_without_prefix() {
+        let output = expand(r#"
+            struct Item<'a> {
+                #[patch(validate(is_known, "unknown type"), min_version = 2)]
+                r#type: String,
+                #[patch(nested)]
+                r#ref: &'a mut Config,
+            }
+        "#);
+        assert!(!output.contains(r#""r#type""#) && !output.contains(r#""r#ref""#));
+        assert!(output.contains(r#"field:"type""#));
+        assert!(output.contains(r#"Borrowed("type")"#) && output.contains(r#"Borrowed("ref")"#));
+    }
+
+    #[test]
+    fn positional_name_is_deprecated() {
+        let positional = expand("#[patch(UserUpdate)] struct User { name: String }");
+        assert!(positional.contains("#[deprecated(") && positional.contains("constUserUpdate:()=();UserUpdate"));
+        let keyed = expand(r#"#[patch(rename_type = "UserUpdate")] struct User { name: String }"#);
+        assert!(!keyed.contains("#[deprecated("));
+    }
+
+    #[test]
+    fn profile_with_patch_name() {
+        let message = "`profile` names its own patch structs and cannot be combined with a patch name";
+        let (single, text) = expand_error(r#"
+            #[patch(rename_type = "UserUpdate", profile(Public, fields(name)))]
+            struct User {
+                name: String,
+            }
+        "#);
+        assert_eq!((single.as_str(), text.as_str()), (message, r#""UserUpdate""#));
+        let (shared, text) = expand_error(r#"
+            #[patch(rename_type = "UserUpdate")]
+            #[patch(rename_type = "UserForm")]
+            #[patch(profile(Public, fields(name)))]
+            struct User {
+                name: String,
+            }
+        "#);
+        assert_eq!((shared.as_str(), text.as_str()), (message, r#""UserUpdate""#));
+    }
+
+    #[test]
+    fn unknown_container_flag() {
+        let (message, text) = expand_error(r#"
+            #[patch(no_imp)]
+            struct User {
+                name: String,
+            }
+        "#);
+        assert_eq!(message, "unknown patch attribute");
+        assert_eq!(text, "no_imp");
+    }
+
+    #[test]
+    fn unsupported_data() {
+        for (source, name) in [("struct Meters(f64);", "Meters"), ("struct Unit;", "Unit"), ("union Bits { int: u32, float: f32 }", "Bits")] {
+            let (message, text) = expand_error(source);
+            assert_eq!(message, UNSUPPORTED_DATA);
+            assert_eq!(text, name);
+        }
+    }
+
+    #[test]
+    fn serde_skips_serializing_empty_if() {
+        let output = expand(r#"
+            #[patch(serde, rename_all = "camelCase")]
+            struct Counter {
+                #[patch(Delta, empty_if = "delta::is_zero")]
+                total: i32,
+            }
+        "#);
+        assert!(output.contains(r#"#[serde(rename_all="camelCase")]"#));
+        assert!(output.contains(r#"#[serde(skip_serializing_if="delta::is_zero")]total:Delta"#));
+    }
+
+    #[test]
+    fn rename_all_requires_serde() {
+        let (message, text) = expand_error(r#"
+            #[patch(rename_all = "camelCase")]
+            struct Profile {
+                display_name: String,
+            }
+        "#);
+        assert_eq!(message, "`rename_all` requires #[patch(serde)]");
+        assert_eq!(text, r#""camelCase""#);
+    }
+
+    #[test]
+    fn alias_requires_serde() {
+        let (message, text) = expand_error(r#"
+            struct Profile {
+                #[patch(alias = "name")]
+                display_name: String,
+            }
+        "#);
+        assert_eq!(message, "`alias` requires #[patch(serde)]");
+        assert_eq!(text, r#""name""#);
+    }
+
+    #[test]
+    fn deny_unknown_fields_requires_serde() {
+        let (message, text) = expand_error(r#"
+            #[patch(deny_unknown_fields)]
+            struct Profile {
+                display_name: String,
+            }
+        "#);
+        assert_eq!(message, "`deny_unknown_fields` requires #[patch(serde)]");
+        assert_eq!(text, "deny_unknown_fields");
+    }
+
+    #[test]
+    fn untagged_requires_serde() {
+        let (message, text) = expand_error(r#"
+            #[patch(untagged)]
+            enum Limit {
+                Fixed(u32),
+            }
+        "#);
+        assert_eq!(message, "`untagged` requires #[patch(serde)]");
+        assert_eq!(text, "untagged");
+    }
+}
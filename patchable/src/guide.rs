@@ -0,0 +1,1337 @@
+//! A guide to the options of [`derive(Patchable)`](macro@crate::Patchable), with an example for each.
+//!
+//! The derive documentation lists every option in short; the sections below go through them in more detail.
+//!
+//! # Naming the patch struct
+//!
+//! Use the `#[patch(rename_type = "PatchType")]` attribute to change the name of the generated struct,
+//! and `#[patch(PatchType)]` to change the type of the replacement field in the generate struct. This works as long as the field type implements `Patchable<PatchType>`.
+//!
+//! This allows for nesting of patches.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! #[patch(rename_type = "MyPatch")]
+//! struct MyStruct {
+//!     foo: String,
+//!     #[patch(BarPatch)]
+//!     bar: Bar,
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Bar {
+//!     foobar: i32,
+//! }
+//! ```
+//!
+//! would generate
+//! ```ignore
+//! struct MyPatch {
+//!     foo: Option<String>,
+//!     bar: BarPatch,
+//! }
+//!
+//! struct BarPatch {
+//!     foobar: Option<i32>,
+//! }
+//! // Patchable impls...
+//! ```
+//!
+//! On the struct, the name can also be given as a bare `#[patch(Name)]`. This form is deprecated, and warns at the
+//! name with a note pointing to `rename_type`, but keeps working for compatibility. Only `UpperCamelCase` words are
+//! taken as a bare name, so a misspelled option is reported as unknown. Both forms may appear in the same attribute
+//! as long as they agree.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! #[patch(rename_type = "UserUpdate", private_fields)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(AccountUpdate, rename_type = "AccountUpdate")]
+//! struct Account {
+//!     id: u32,
+//! }
+//!
+//! let mut user = User { name: String::from("a") };
+//! user.apply_patch(UserUpdate::unchanged());
+//! let mut account = Account { id: 1 };
+//! account.apply_patch(AccountUpdate { id: Some(2) });
+//! assert_eq!((user.name.as_str(), account.id), ("a", 2));
+//! ```
+//!
+//! Several `#[patch(...)]` attributes that each name a patch struct generate one patch struct each, with their own
+//! options, such as a plain patch and one for serde. Container attributes that do not name a patch struct apply to
+//! all of them, as do the field attributes. Only the first named patch is the one named by `HasPatch`.
+//!
+//! ```
+//! # use patchable::{HasPatch, Patchable};
+//! #[derive(Patchable)]
+//! #[patch(rename_type = "UserPatch")]
+//! #[patch(rename_type = "UserFormPatch", private_fields)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! let mut user = User { name: String::from("a") };
+//! user.apply_patch(UserPatch { name: Some(String::from("b")) });
+//! user.apply_patch(UserFormPatch { name: Some(String::from("c")) });
+//! let patch: <User as HasPatch>::Patch = UserPatch { name: None };
+//! user.apply_patch(patch);
+//! assert_eq!(user.name, "c");
+//! ```
+//!
+//! To expose different subsets of a struct, use `#[patch(profile(Name, fields(...)))]`.
+//! Each profile generates a `NamePatch` struct containing only the listed fields, with its own `Patchable` impl.
+//! When any profile is given, no default patch struct is generated, and naming a patch struct as well is an error.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! #[patch(profile(User, fields(name, bio)))]
+//! #[patch(profile(Admin, fields(name, bio, role)))]
+//! struct Account {
+//!     name: String,
+//!     bio: String,
+//!     role: String,
+//! }
+//!
+//! let mut account = Account { name: "a".into(), bio: "".into(), role: "user".into() };
+//! account.apply_patch(UserPatch { name: None, bio: Some("hi".into()) });
+//! account.apply_patch(AdminPatch { name: None, bio: None, role: Some("admin".into()) });
+//! assert_eq!(account.bio, "hi");
+//! assert_eq!(account.role, "admin");
+//! ```
+//!
+//! # Visibility and attributes
+//!
+//! The patch struct and its fields copy the visibility of the source struct and its fields, including restricted
+//! visibilities such as `pub(crate)`, `pub(super)` and `pub(in path)`.
+//!
+//! ```
+//! mod outer {
+//!     pub mod inner {
+//!         # use patchable::Patchable;
+//!         #[derive(Patchable)]
+//!         pub struct MyStruct {
+//!             pub a: i32,
+//!             pub(crate) b: i32,
+//!             pub(super) c: i32,
+//!             pub(in crate::outer) d: i32,
+//!         }
+//!     }
+//!
+//!     pub fn run() {
+//!         # use patchable::Patchable;
+//!         let mut value = inner::MyStruct { a: 0, b: 0, c: 0, d: 0 };
+//!         value.apply_patch(inner::MyStructPatch { a: None, b: None, c: Some(3), d: Some(4) });
+//!         assert_eq!((value.c, value.d), (3, 4));
+//!     }
+//! }
+//!
+//! fn main() {
+//!     outer::run();
+//! }
+//! ```
+//!
+//! With `#[patch(private_fields)]`, the patch fields are private whatever the visibility of the source fields, while
+//! the patch struct keeps the visibility of the source struct. Code outside the module can still name the patch type,
+//! but has to create patches through constructors defined next to it, `From` impls, `Patch::empty` or deserialization.
+//!
+//! ```
+//! mod user {
+//!     # use patchable::Patchable;
+//!     #[derive(Patchable)]
+//!     #[patch(private_fields)]
+//!     pub struct User {
+//!         pub name: String,
+//!     }
+//!
+//!     impl UserPatch {
+//!         pub fn rename(name: &str) -> Self {
+//!             UserPatch { name: Some(name.trim().to_string()) }
+//!         }
+//!     }
+//! }
+//!
+//! # use patchable::Patchable;
+//! let mut user = user::User { name: String::from("a") };
+//! user.apply_patch(user::UserPatch::rename(" b "));
+//! assert_eq!(user.name, "b");
+//! ```
+//!
+//! ```compile_fail
+//! mod user {
+//!     # use patchable::Patchable;
+//!     #[derive(Patchable)]
+//!     #[patch(private_fields)]
+//!     pub struct User {
+//!         pub name: String,
+//!     }
+//! }
+//!
+//! let patch = user::UserPatch { name: None };
+//! ```
+//!
+//! By default, attributes on the source struct are not copied to the patch struct.
+//! Adding `#[patch(forward_attrs)]` re-emits every non-`patch` attribute, such as derives, onto the patch struct.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! #[patch(forward_attrs)]
+//! #[derive(Debug, Default)]
+//! struct MyStruct {
+//!     foo: String,
+//! }
+//!
+//! let patch = MyStructPatch::default();
+//! assert!(patch.foo.is_none());
+//! ```
+//!
+//! A patch field can be given another name than its source field with `#[patch(rename = "name")]`.
+//! It is an error for two fields to end up with the same patch field name.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct MyStruct {
+//!     #[patch(rename = "title")]
+//!     name: String,
+//! }
+//!
+//! let mut value = MyStruct { name: String::from("old") };
+//! value.apply_patch(MyStructPatch { title: Some(String::from("new")) });
+//! assert_eq!(value.name, "new");
+//! ```
+//!
+//! ```compile_fail
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct MyStruct {
+//!     title: String,
+//!     #[patch(rename = "title")]
+//!     name: String,
+//! }
+//! ```
+//!
+//! Renamed or retired fields can be marked with `#[patch(deprecated = "note")]`.
+//! The generated patch field gets a `#[deprecated(note = "note")]` attribute, so downstream code constructing it is warned,
+//! but the field is still applied as usual.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct MyStruct {
+//!     #[patch(deprecated = "use `name` instead")]
+//!     title: String,
+//!     name: String,
+//! }
+//! ```
+//!
+//! `#[patch(feature = "name")]` on a field only generates its patch field when the cargo feature `name` of the crate
+//! using the derive is enabled. Without the feature, the source field still exists but is never patched, and it is
+//! left out of every generated item, such as `Patch::empty`, validation and `From` impls.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct MyStruct {
+//!     foo: i32,
+//!     #[patch(feature = "extra")]
+//!     bar: i32,
+//! }
+//!
+//! let mut value = MyStruct { foo: 0, bar: 0 };
+//! # #[cfg(not(feature = "extra"))]
+//! value.apply_patch(MyStructPatch { foo: Some(1) });
+//! # #[cfg(feature = "extra")]
+//! # value.apply_patch(MyStructPatch { foo: Some(1), bar: None });
+//! assert_eq!(value.foo, 1);
+//! ```
+//!
+//! With the feature enabled, the field is patched like any other. The example below runs with the `test-util` feature
+//! of this crate, which stands in for a feature of the user's crate here:
+//!
+#![cfg_attr(feature = "test-util", doc = "```")]
+#![cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct MyStruct {
+//!     #[patch(feature = "test-util")]
+//!     bar: i32,
+//! }
+//!
+//! let mut value = MyStruct { bar: 0 };
+//! value.apply_patch(MyStructPatch { bar: Some(1) });
+//! assert_eq!(value.bar, 1);
+//! assert!(!MyStructPatch { bar: Some(1) }.is_noop());
+//! ```
+//!
+//! # Patch types of fields
+//!
+//! Fields without a patch type are replaced as a whole, through an `Option` of the field type.
+//! This is also how enum fields are patched; `#[patch(replace)]` states it explicitly,
+//! and guarantees the `Option` patch field regardless of other defaults.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! # use std::net::{IpAddr, Ipv4Addr};
+//! #[derive(Patchable)]
+//! struct Server {
+//!     #[patch(replace)]
+//!     addr: IpAddr,
+//! }
+//!
+//! let mut server = Server { addr: IpAddr::V4(Ipv4Addr::LOCALHOST) };
+//! server.apply_patch(ServerPatch { addr: Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)) });
+//! assert_eq!(server.addr, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+//! ```
+//!
+//! The patch type can be any type, including a generic patch struct, whose arguments may use the generics of the
+//! source struct.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct Bar<T> {
+//!     foobar: T,
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct MyStruct<T> {
+//!     #[patch(BarPatch<u32>)]
+//!     fixed: Bar<u32>,
+//!     #[patch(BarPatch<T>)]
+//!     generic: Bar<T>,
+//! }
+//!
+//! let mut value = MyStruct { fixed: Bar { foobar: 1 }, generic: Bar { foobar: "a" } };
+//! value.apply_patch(MyStructPatch {
+//!     fixed: BarPatch { foobar: Some(2) },
+//!     generic: BarPatch { foobar: Some("b") },
+//! });
+//! assert_eq!((value.fixed.foobar, value.generic.foobar), (2, "b"));
+//! ```
+//!
+//! The patch type can also be given as a string with `#[patch(ty = "Type")]`, which is used verbatim.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! # #[derive(Patchable)]
+//! # struct Bar<T> {
+//! #     foobar: T,
+//! # }
+//! #[derive(Patchable)]
+//! struct History {
+//!     #[patch(ty = "Vec<BarPatch<u32>>")]
+//!     bar: Bar<u32>,
+//! }
+//!
+//! let mut history = History { bar: Bar { foobar: 0 } };
+//! history.apply_patch(HistoryPatch { bar: vec![BarPatch { foobar: Some(1) }, BarPatch { foobar: Some(2) }] });
+//! assert_eq!(history.bar.foobar, 2);
+//! ```
+//!
+//! A field holding a `&mut T` can be given a patch type, which then applies to the `T` behind the reference.
+//! A shared reference cannot be patched through, so such a field can only be replaced as a whole.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct Config {
+//!     level: u8,
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Session<'a> {
+//!     name: &'a str,
+//!     #[patch(nested)]
+//!     config: &'a mut Config,
+//! }
+//!
+//! let mut config = Config { level: 1 };
+//! let mut session = Session { name: "a", config: &mut config };
+//! session.apply_patch(SessionPatch { name: Some("b"), config: ConfigPatch { level: Some(2) } });
+//! assert_eq!(session.name, "b");
+//! assert_eq!(config.level, 2);
+//! ```
+//!
+//! Incoming values can be converted before they replace the field with `#[patch(map = "path::to::fn", input = "In")]`,
+//! where the function is a `fn(In) -> T` for a field of type `T`. The patch field is then an `Option<In>`, and
+//! validators check the incoming `In`.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! fn km_to_m(km: f64) -> f64 {
+//!     km * 1000.0
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Route {
+//!     #[patch(map = "km_to_m", input = "f64")]
+//!     length_m: f64,
+//! }
+//!
+//! let mut route = Route { length_m: 0.0 };
+//! route.apply_patch(RoutePatch { length_m: Some(1.5) });
+//! assert_eq!(route.length_m, 1500.0);
+//! ```
+//!
+//! An `Option<T>` field is patched with an `Option<Option<T>>` by default. With `#[patch(flatten_option)]`, its patch
+//! field is an `Option<T>` instead, and `Some(value)` sets the field to `Some(value)`. Such a patch cannot set the field
+//! to `None`.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct User {
+//!     #[patch(flatten_option)]
+//!     nickname: Option<String>,
+//! }
+//!
+//! let mut user = User { nickname: None };
+//! user.apply_patch(UserPatch { nickname: Some(String::from("a")) });
+//! user.apply_patch(UserPatch { nickname: None });
+//! assert_eq!(user.nickname.as_deref(), Some("a"));
+//! ```
+//!
+//! A `Vec<T>` or `Option<Vec<T>>` field marked `#[patch(list_or_clear)]` is patched with a
+//! [`ListPatch<T>`](enum@crate::ListPatch), which can keep, replace or clear the list.
+//!
+//! ```
+//! # use patchable::{ListPatch, Patchable};
+//! #[derive(Patchable)]
+//! struct Post {
+//!     #[patch(list_or_clear)]
+//!     tags: Option<Vec<String>>,
+//! }
+//!
+//! let mut post = Post { tags: None };
+//! post.apply_patch(PostPatch { tags: ListPatch::Clear });
+//! assert_eq!(post.tags, Some(vec![]));
+//! ```
+//!
+//! `#[patch(nested_option)]` on an `Option<T>` field patches the value inside it with an
+//! [`InnerPatch`](crate::InnerPatch) of `T`'s patch, which does nothing while the field is `None`.
+//! With `#[patch(nested_option, init_default)]` it is an [`InitPatch`](crate::InitPatch) instead, which
+//! starts from `T::default()` when the field is `None`. An empty patch leaves a `None` field as `None`, so that
+//! patches which do not touch the field do not create a default value; the field only becomes `Some` once the patch
+//! sets at least one of its fields.
+//!
+//! ```
+//! # use patchable::{InitPatch, Patchable};
+//! #[derive(Patchable, Default)]
+//! struct Proxy {
+//!     host: String,
+//!     port: u16,
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Config {
+//!     #[patch(nested_option, init_default)]
+//!     proxy: Option<Proxy>,
+//! }
+//!
+//! let mut config = Config { proxy: None };
+//! config.apply_patch(ConfigPatch { proxy: InitPatch(ProxyPatch::unchanged()) });
+//! assert!(config.proxy.is_none());
+//!
+//! config.apply_patch(ConfigPatch { proxy: InitPatch(ProxyPatch { host: None, port: Some(8080) }) });
+//! let proxy = config.proxy.unwrap();
+//! assert_eq!((proxy.host.as_str(), proxy.port), ("", 8080));
+//! ```
+//!
+//! # Generics
+//!
+//! Generic structs are supported, and the patch struct has the same generic parameters.
+//! A field marked `#[patch(nested)]` uses the patch type named by its [`HasPatch`](trait@crate::HasPatch) impl,
+//! which the derive implements for every struct it generates a patch for.
+//! Extra bounds for the generated items can be added with `#[patch(bound = "...")]`.
+//!
+//! ```
+//! # use patchable::{HasPatch, Patchable};
+//! #[derive(Patchable)]
+//! #[patch(bound = "T: HasPatch")]
+//! struct Wrapper<T> {
+//!     #[patch(nested)]
+//!     value: T,
+//!     count: u32,
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Element {
+//!     foo: i32,
+//! }
+//!
+//! let mut wrapper = Wrapper { value: Element { foo: 1 }, count: 0 };
+//! wrapper.apply_patch(WrapperPatch { value: ElementPatch { foo: Some(2) }, count: None });
+//! assert_eq!(wrapper.value.foo, 2);
+//! ```
+//!
+//! The derive adds no bounds on the generic parameters itself. The impls only need what their fields need, so a
+//! parameter that is only used in a field like `PhantomData<T>` does not have to be patchable.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! # use std::marker::PhantomData;
+//! struct Unpatchable;
+//!
+//! #[derive(Patchable)]
+//! struct Id<T> {
+//!     value: u32,
+//!     marker: PhantomData<T>,
+//! }
+//!
+//! let mut id: Id<Unpatchable> = Id { value: 1, marker: PhantomData };
+//! id.apply_patch(IdPatch { value: Some(2), marker: None });
+//! assert_eq!(id.value, 2);
+//! ```
+//!
+//! Default type parameters are kept on the patch struct, so it can be named without them as well.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! #[patch(forward_attrs)]
+//! #[derive(Default)]
+//! struct Settings<T = String> {
+//!     value: T,
+//! }
+//!
+//! let patch: SettingsPatch = SettingsPatch::default();
+//! let mut settings: Settings = Settings { value: String::from("a") };
+//! settings.apply_patch(patch);
+//! assert_eq!(settings.value, "a");
+//! ```
+//!
+//! # The patch struct
+//!
+//! The patch struct implements [`Patch`](trait@crate::Patch) when every field's patch type does,
+//! and is empty when every field is empty.
+//! A field can decide its own emptiness with `#[patch(empty_if = "path::to::fn")]`, where the function takes a reference
+//! to the field's patch type. This is needed for patch types that do not implement `Patch`, or that can be set
+//! to a value that changes nothing. Such a field is created with `Default` in `Patch::empty`.
+//!
+//! The patch struct also gets `unchanged()` and `is_noop()`, which are the same as `Patch::empty` and `Patch::is_empty`
+//! but read better at call sites, and `fields_set()`, which counts the fields that are not empty.
+//! Its `FIELD_COUNT` constant is the number of patch fields, and a `NAME_INDEX` constant for each field, such as
+//! `TOTAL_INDEX` for a field `total`, numbers them in declaration order.
+//! `Patch::count_changes` adds up the changes of every field, counting the fields of nested patches one by one, which
+//! `Patchable::apply_patch_counting` uses to keep a running total of changed values.
+//! `Patch::clear` resets every field in place, clearing nested patches field by field, so a patch can be reused.
+//!
+//! ```
+//! # use patchable::{Patch, Patchable};
+//! #[derive(Default)]
+//! struct Delta(i32);
+//!
+//! impl Patchable<Delta> for i32 {
+//!     fn apply_patch(&mut self, patch: Delta) {
+//!         *self += patch.0;
+//!     }
+//! }
+//!
+//! fn is_zero(delta: &Delta) -> bool {
+//!     delta.0 == 0
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Counter {
+//!     #[patch(Delta, empty_if = "is_zero")]
+//!     total: i32,
+//!     name: String,
+//! }
+//!
+//! assert!(CounterPatch { total: Delta(0), name: None }.is_empty());
+//! assert!(!CounterPatch { total: Delta(1), name: None }.is_empty());
+//! assert!(CounterPatch::unchanged().is_noop());
+//! assert_eq!((CounterPatch::FIELD_COUNT, CounterPatch::NAME_INDEX), (2, 1));
+//! assert_eq!(CounterPatch { total: Delta(1), name: Some(String::from("a")) }.fields_set(), 2);
+//!
+//! let mut counter = Counter { total: 0, name: String::new() };
+//! let mut changed = 0;
+//! counter.apply_patch_counting(CounterPatch { total: Delta(1), name: None }, &mut changed);
+//! counter.apply_patch_counting(CounterPatch { total: Delta(1), name: Some(String::from("a")) }, &mut changed);
+//! assert_eq!(changed, 3);
+//!
+//! let mut patch = CounterPatch { total: Delta(1), name: Some(String::from("a")) };
+//! patch.clear();
+//! assert!(patch.is_noop());
+//! ```
+//!
+//! The patch struct implements `Clone` when every field's patch type does, so
+//! [`Patchable::apply_patch_ref`](crate::Patchable::apply_patch_ref) can apply it without consuming it. With
+//! `forward_attrs`, a forwarded `#[derive(Clone)]` is used instead. The source struct also implements `Patchable<&NamePatch>`, which only clones the fields of the patch that are not empty, so a
+//! patch that sets few fields can be applied to many values cheaply. It requires the fields' patch types to implement
+//! [`Patch`](trait@crate::Patch) as well, or the fields to have `empty_if`.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct Player {
+//!     name: String,
+//!     score: u32,
+//! }
+//!
+//! let patch = PlayerPatch { name: None, score: Some(10) };
+//! let mut players = vec![
+//!     Player { name: String::from("a"), score: 1 },
+//!     Player { name: String::from("b"), score: 2 },
+//! ];
+//! for player in &mut players {
+//!     player.apply_patch(&patch);
+//! }
+//! players[0].apply_patch_ref(&patch);
+//! assert!(players.iter().all(|player| player.score == 10));
+//! assert_eq!(players[1].name, "b");
+//!
+//! #[derive(Patchable)]
+//! #[patch(forward_attrs)]
+//! #[derive(Debug, Clone)]
+//! struct Team {
+//!     name: String,
+//! }
+//!
+//! let patch = TeamPatch { name: Some(String::from("b")) };
+//! let mut team = Team { name: String::from("a") };
+//! team.apply_patch(&patch);
+//! team.apply_patch_ref(&patch);
+//! assert_eq!(team.name, "b");
+//! ```
+//!
+//! A patch can also be applied to another patch of the same type, which merges them: fields patched with an `Option`
+//! take the incoming value when it is `Some` and keep their own otherwise, and other fields are merged by applying the
+//! incoming field to them, which requires their patch type to be patchable with itself.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct User {
+//!     name: String,
+//!     age: u32,
+//! }
+//!
+//! let mut patch = UserPatch { name: Some(String::from("a")), age: Some(1) };
+//! patch.apply_patch(UserPatch { name: None, age: Some(2) });
+//! assert_eq!(patch.name.as_deref(), Some("a"));
+//! assert_eq!(patch.age, Some(2));
+//! ```
+//!
+//! When the patch struct also implements [`Patch`](trait@crate::Patch), a sequence of patches can be
+//! collected into one, starting from an empty patch and merging each in turn, so later patches win.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! # #[derive(Patchable)]
+//! # struct User {
+//! #     name: String,
+//! #     age: u32,
+//! # }
+//! let patches = vec![
+//!     UserPatch { name: Some(String::from("a")), age: Some(1) },
+//!     UserPatch { name: None, age: Some(2) },
+//!     UserPatch { name: Some(String::from("c")), age: None },
+//! ];
+//! let merged: UserPatch = patches.into_iter().collect();
+//! assert_eq!(merged.name.as_deref(), Some("c"));
+//! assert_eq!(merged.age, Some(2));
+//! ```
+//!
+//! The patch struct implements [`VisitPatch`](trait@crate::VisitPatch) when every field's patch type does,
+//! calling a [`PatchVisitor`](trait@crate::PatchVisitor) with the name and value of each field that is set.
+//! Nested patches are visited field by field, with dotted names.
+//!
+//! ```
+//! # use patchable::{PatchVisitor, Patchable, VisitPatch};
+//! struct Names(Vec<String>);
+//!
+//! impl PatchVisitor for Names {
+//!     fn visit<T: ?Sized>(&mut self, name: &str, _value: &T) {
+//!         self.0.push(name.to_string());
+//!     }
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Address {
+//!     city: String,
+//!     street: String,
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct User {
+//!     name: String,
+//!     #[patch(nested)]
+//!     address: Address,
+//! }
+//!
+//! let patch = UserPatch {
+//!     name: Some(String::from("a")),
+//!     address: AddressPatch { city: Some(String::from("b")), street: None },
+//! };
+//! let mut names = Names(Vec::new());
+//! patch.visit_set(&mut names);
+//! assert_eq!(names.0, ["name", "address.city"]);
+//! ```
+//!
+//! `#[patch(from = "Type")]` implements `From<Type>` for the patch struct, for building patches from another type with the
+//! same field names, such as a request type. Each field is converted with `Into`, and fields patched with an `Option`
+//! are wrapped in `Some`. Every patch field must exist on the other type.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! struct ApiUser {
+//!     name: &'static str,
+//!     age: u8,
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(from = "ApiUser")]
+//! struct User {
+//!     name: String,
+//!     age: u32,
+//! }
+//!
+//! let patch = UserPatch::from(ApiUser { name: "new", age: 30 });
+//! assert_eq!(patch.name.as_deref(), Some("new"));
+//! assert_eq!(patch.age, Some(30));
+//! ```
+//!
+//! `#[patch(key)]` on one field marks it as the identity of the struct, implementing
+//! [`PatchKey`](crate::PatchKey) to return it, for patches that find elements of a list by key.
+//!
+//! ```
+//! # use patchable::{PatchKey, Patchable};
+//! #[derive(Patchable)]
+//! struct Item {
+//!     #[patch(key)]
+//!     id: u32,
+//!     name: String,
+//! }
+//!
+//! let item = Item { id: 7, name: String::from("a") };
+//! assert_eq!(*item.patch_key(), 7);
+//! ```
+//!
+//! With the `test-util` feature of `patchable`, `#[patch(examples)]` adds `NamePatch::example()`, which creates a
+//! patch for test fixtures that sets the first field patched with an `Option` to `Some` of its default value, or for
+//! a `nested` field, to the `example()` of its patch. Fields behind a `feature` are skipped, so the example is the
+//! same on every build. A chosen `Option` field's value type must implement `Default`, and a chosen `nested` field's
+//! patch must have `example()` as well. The other fields are left empty, which requires their patch types to implement
+//! [`Patch`](trait@crate::Patch).
+//!
+#![cfg_attr(feature = "test-util", doc = "```")]
+#![cfg_attr(not(feature = "test-util"), doc = "```ignore")]
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! #[patch(examples)]
+//! struct Address {
+//!     city: String,
+//!     zip: u32,
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(examples)]
+//! struct User {
+//!     #[patch(nested)]
+//!     address: Address,
+//!     name: String,
+//! }
+//!
+//! let example = UserPatch::example();
+//! assert_eq!(example.address.city.as_deref(), Some(""));
+//! assert_eq!((example.address.zip, example.name), (None, None));
+//!
+//! #[derive(Patchable)]
+//! #[patch(examples)]
+//! struct Wrapper<T> {
+//!     value: T,
+//! }
+//!
+//! assert_eq!(WrapperPatch::<u8>::example().value, Some(0));
+//! ```
+//!
+//! # Applying patches
+//!
+//! The generated code refers to the trait by its full path, so it does not need to be imported,
+//! and structs declared inside function bodies can be derived as well.
+//! Items are looked up through `::patchable`; if the crate is available under another path,
+//! set it with `#[patch(crate = "path")]`.
+//!
+//! ```
+//! fn local() {
+//!     use patchable as renamed;
+//!
+//!     #[derive(renamed::Patchable)]
+//!     #[patch(crate = "renamed")]
+//!     struct Local {
+//!         foo: i32,
+//!     }
+//!
+//!     let mut value = Local { foo: 1 };
+//!     renamed::Patchable::apply_patch(&mut value, LocalPatch { foo: Some(2) });
+//!     assert_eq!(value.foo, 2);
+//! }
+//! # local();
+//! ```
+//!
+//! The patch can apply to another struct than the one it is derived from with `#[patch(target = "Type")]`.
+//! Fields are matched by name, so every field of the source struct must also exist on the target.
+//! No `HasPatch` impl is generated in this case.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! #[patch(target = "Domain")]
+//! struct View {
+//!     name: String,
+//! }
+//!
+//! struct Domain {
+//!     id: u32,
+//!     name: String,
+//! }
+//!
+//! let mut domain = Domain { id: 1, name: String::from("old") };
+//! domain.apply_patch(ViewPatch { name: Some(String::from("new")) });
+//! assert_eq!(domain.name, "new");
+//! ```
+//!
+//! Fields are applied in the order they are declared in the source struct.
+//! When a field has to be applied before others, list it in `#[patch(order(...))]`:
+//! the listed fields are applied first, in the given order, followed by the rest in declaration order.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! # use std::sync::Mutex;
+//! static LOG: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+//!
+//! struct Recorder;
+//! struct Record(&'static str);
+//!
+//! impl Patchable<Record> for Recorder {
+//!     fn apply_patch(&mut self, patch: Record) {
+//!         LOG.lock().unwrap().push(patch.0);
+//!     }
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Declared {
+//!     #[patch(Record)]
+//!     a: Recorder,
+//!     #[patch(Record)]
+//!     b: Recorder,
+//!     #[patch(Record)]
+//!     c: Recorder,
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(order(c))]
+//! struct Ordered {
+//!     #[patch(Record)]
+//!     a: Recorder,
+//!     #[patch(Record)]
+//!     b: Recorder,
+//!     #[patch(Record)]
+//!     c: Recorder,
+//! }
+//!
+//! Declared { a: Recorder, b: Recorder, c: Recorder }
+//!     .apply_patch(DeclaredPatch { a: Record("a"), b: Record("b"), c: Record("c") });
+//! assert_eq!(*LOG.lock().unwrap(), ["a", "b", "c"]);
+//!
+//! LOG.lock().unwrap().clear();
+//! Ordered { a: Recorder, b: Recorder, c: Recorder }
+//!     .apply_patch(OrderedPatch { a: Record("a"), b: Record("b"), c: Record("c") });
+//! assert_eq!(*LOG.lock().unwrap(), ["c", "a", "b"]);
+//! ```
+//!
+//! When a field depends on the patched value of other fields, `#[patch(after = "other")]` applies it after them,
+//! and can be repeated for several fields. The fields are sorted so that every field comes after its dependencies,
+//! and otherwise keep the order from `order(...)` and then declaration order. A cycle of dependencies is an error.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! # use std::sync::Mutex;
+//! # static LOG: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+//! # struct Recorder;
+//! # struct Record(&'static str);
+//! # impl Patchable<Record> for Recorder {
+//! #     fn apply_patch(&mut self, patch: Record) {
+//! #         LOG.lock().unwrap().push(patch.0);
+//! #     }
+//! # }
+//! #[derive(Patchable)]
+//! struct Dependent {
+//!     #[patch(Record, after = "c")]
+//!     a: Recorder,
+//!     #[patch(Record, after = "a", after = "c")]
+//!     b: Recorder,
+//!     #[patch(Record)]
+//!     c: Recorder,
+//! }
+//!
+//! Dependent { a: Recorder, b: Recorder, c: Recorder }
+//!     .apply_patch(DependentPatch { a: Record("a"), b: Record("b"), c: Record("c") });
+//! assert_eq!(*LOG.lock().unwrap(), ["c", "a", "b"]);
+//! ```
+//!
+//! `#[patch(post_apply = "path::to::fn")]` calls a `fn(&mut T)` with the patched value once at the end of every
+//! `apply_patch`, after all fields are set and before `on_apply`, to recompute derived fields or restore invariants.
+//! It runs even when the patch was empty.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! fn update_total(order: &mut Order) {
+//!     order.total = order.price * order.quantity;
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(post_apply = "update_total")]
+//! struct Order {
+//!     price: u32,
+//!     quantity: u32,
+//!     total: u32,
+//! }
+//!
+//! let mut order = Order { price: 5, quantity: 1, total: 5 };
+//! order.apply_patch(OrderPatch { price: None, quantity: Some(3), total: None });
+//! assert_eq!(order.total, 15);
+//! ```
+//!
+//! `#[patch(inspect = "path::to::fn")]` on a field calls a `fn(&T)` with the field, of type `T`, right after it is
+//! patched, but only if its patch was not empty, so a `None` field does not call it. Like `on_apply`, this requires
+//! the field's patch type to implement [`Patch`](trait@crate::Patch), or the field to have `empty_if`.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! # use std::sync::Mutex;
+//! static INDEXED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+//!
+//! fn reindex(title: &String) {
+//!     INDEXED.lock().unwrap().push(title.clone());
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Document {
+//!     #[patch(inspect = "reindex")]
+//!     title: String,
+//!     body: String,
+//! }
+//!
+//! let mut document = Document { title: String::from("a"), body: String::new() };
+//! document.apply_patch(DocumentPatch { title: None, body: Some(String::from("text")) });
+//! document.apply_patch(DocumentPatch { title: Some(String::from("b")), body: None });
+//! assert_eq!(*INDEXED.lock().unwrap(), ["b"]);
+//! ```
+//!
+//! `#[patch(on_apply = "path::to::fn")]` calls a `fn(changed: usize)` at the end of every `apply_patch`,
+//! with the number of fields that were not empty in the patch. This requires every field's patch type to implement
+//! [`Patch`](trait@crate::Patch), or the field to have `empty_if`.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! # use std::sync::atomic::{AtomicUsize, Ordering};
+//! static CHANGED: AtomicUsize = AtomicUsize::new(0);
+//!
+//! fn count(changed: usize) {
+//!     CHANGED.fetch_add(changed, Ordering::Relaxed);
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(on_apply = "count")]
+//! struct MyStruct {
+//!     foo: i32,
+//!     bar: i32,
+//! }
+//!
+//! let mut value = MyStruct { foo: 0, bar: 0 };
+//! value.apply_patch(MyStructPatch { foo: Some(1), bar: None });
+//! assert_eq!(CHANGED.load(Ordering::Relaxed), 1);
+//! ```
+//!
+//! `#[patch(changes)]` generates a `NameChange` enum with a variant per field, named in `PascalCase`, holding the
+//! `old` and `new` values of the field, and an `apply_patch_changes` method that applies a patch and returns the
+//! changes for the fields whose patch was not empty. The enum derives `Debug`, `Clone` and `PartialEq`, so the field
+//! types need to implement them, and like `on_apply`, the fields' patch types need to implement
+//! [`Patch`](trait@crate::Patch). A field holding a `&mut T` records the values of type `T`, and lifetimes
+//! that only such references use are left out of the enum and of the patch struct.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable, Debug, PartialEq)]
+//! #[patch(changes)]
+//! struct User {
+//!     name: String,
+//!     login_count: u32,
+//! }
+//!
+//! let mut user = User { name: String::from("a"), login_count: 1 };
+//! let changes = user.apply_patch_changes(UserPatch { name: None, login_count: Some(2) });
+//! assert_eq!(changes, [UserChange::LoginCount { old: 1, new: 2 }]);
+//!
+//! #[derive(Patchable, Clone, Debug, PartialEq)]
+//! struct Config {
+//!     level: u8,
+//! }
+//!
+//! #[derive(Patchable, Debug)]
+//! #[patch(changes)]
+//! struct Session<'a> {
+//!     #[patch(nested)]
+//!     config: &'a mut Config,
+//! }
+//!
+//! let mut config = Config { level: 1 };
+//! let mut session = Session { config: &mut config };
+//! let changes = session.apply_patch_changes(SessionPatch { config: ConfigPatch { level: Some(2) } });
+//! assert_eq!(changes, [SessionChange::Config { old: Config { level: 1 }, new: Config { level: 2 } }]);
+//! ```
+//!
+//! `#[patch(no_impl)]` generates the patch struct and its other impls, but not the `Patchable` and `TryPatchable`
+//! impls or `HasPatch`, so that applying the patch can be written by hand. Options that only change how the patch is
+//! applied, such as `order`, `inspect` and `on_apply`, then have no effect.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! #[patch(no_impl)]
+//! struct Counter {
+//!     count: u32,
+//! }
+//!
+//! impl Patchable<CounterPatch> for Counter {
+//!     fn apply_patch(&mut self, patch: CounterPatch) {
+//!         if let Some(count) = patch.count {
+//!             self.count = self.count.max(count);
+//!         }
+//!     }
+//! }
+//!
+//! let mut counter = Counter { count: 5 };
+//! counter.apply_patch(CounterPatch { count: Some(3) });
+//! counter.apply_patch(CounterPatch { count: Some(8) });
+//! assert_eq!(counter.count, 8);
+//! ```
+//!
+//! # Validation
+//!
+//! Every derive also implements [`TryPatchable`](trait@crate::TryPatchable).
+//! Fields patched with an `Option` can be checked with `#[patch(validate(|value| ..., "message"))]`, where the closure
+//! receives a reference to the incoming value and must not capture anything.
+//! `try_apply_patch` runs every check before changing anything, and returns
+//! [`PatchError::Validation`](enum@crate::PatchError) naming the field and message of the first failed check.
+//! `apply_patch` does not run the checks.
+//!
+//! ```
+//! # use patchable::{PatchError, Patchable, TryPatchable};
+//! #[derive(Patchable)]
+//! struct Counter {
+//!     #[patch(validate(|value| *value > 0, "must be positive"))]
+//!     step: i32,
+//!     name: String,
+//! }
+//!
+//! let mut counter = Counter { step: 1, name: String::from("a") };
+//! let result = counter.try_apply_patch(CounterPatch { step: Some(0), name: Some(String::from("b")) });
+//! assert_eq!(result, Err(PatchError::Validation { field: "step", message: "must be positive" }));
+//! assert_eq!(counter.name, "a");
+//!
+//! counter.try_apply_patch(CounterPatch { step: Some(2), name: None }).unwrap();
+//! assert_eq!(counter.step, 2);
+//! ```
+//!
+//! A check can also be the path of a function taking a reference to the value.
+//!
+//! ```
+//! # use patchable::{PatchError, Patchable, TryPatchable};
+//! fn check(value: &u32) -> bool {
+//!     *value > 0
+//! }
+//!
+//! #[derive(Patchable)]
+//! struct Limit {
+//!     #[patch(validate(check, "must be positive"))]
+//!     max: u32,
+//! }
+//!
+//! let result = Limit { max: 1 }.try_apply_patch(LimitPatch { max: Some(0) });
+//! assert_eq!(result, Err(PatchError::Validation { field: "max", message: "must be positive" }));
+//! ```
+//!
+//! For versioned APIs, `#[patch(min_version = N)]` marks a field as only accepted from clients of version `N` or later.
+//! The patch struct gets `check_version(version)`, which returns
+//! [`PatchError::UnsupportedField`](enum@crate::PatchError) naming the first field that is set but not
+//! accepted from `version`. Such a field's patch type must implement [`Patch`](trait@crate::Patch), or the
+//! field must have `empty_if`.
+//!
+//! ```
+//! # use patchable::{PatchError, Patchable};
+//! #[derive(Patchable)]
+//! struct Account {
+//!     name: String,
+//!     #[patch(min_version = 2)]
+//!     nickname: String,
+//! }
+//!
+//! let patch = AccountPatch { name: Some(String::from("a")), nickname: Some(String::from("b")) };
+//! assert_eq!(patch.check_version(2), Ok(()));
+//! assert_eq!(
+//!     patch.check_version(1),
+//!     Err(PatchError::UnsupportedField { field: "nickname", min_version: 2, version: 1 }),
+//! );
+//! ```
+//!
+//! Fields with the same `#[patch(group = "name")]` are only applied together: if some but not all of them are set
+//! in a patch, `apply_patch` leaves all of them out, and `try_apply_patch` returns
+//! [`PatchError::PartialGroup`](crate::PatchError::PartialGroup) with the fields that are missing.
+//! Like `on_apply`, this requires the patch types of the fields to implement
+//! [`Patch`](trait@crate::Patch), or the fields to have `empty_if`.
+//!
+//! ```
+//! # use patchable::{PatchError, Patchable, TryPatchable};
+//! #[derive(Patchable)]
+//! struct Window {
+//!     #[patch(group = "size")]
+//!     width: u32,
+//!     #[patch(group = "size")]
+//!     height: u32,
+//!     title: String,
+//! }
+//!
+//! let mut window = Window { width: 640, height: 480, title: String::from("a") };
+//! window.apply_patch(WindowPatch { width: Some(800), height: None, title: Some(String::from("b")) });
+//! assert_eq!((window.width, window.height, window.title.as_str()), (640, 480, "b"));
+//!
+//! let result = window.try_apply_patch(WindowPatch { width: Some(800), height: None, title: None });
+//! assert_eq!(result, Err(PatchError::PartialGroup { group: "size", missing: vec!["height"] }));
+//!
+//! window.apply_patch(WindowPatch { width: Some(800), height: Some(600), title: None });
+//! assert_eq!((window.width, window.height), (800, 600));
+//! ```
+//!
+//! The fields of a group that is left out do not count as changed either, for `on_apply`,
+//! [`Patch::count_changes`](crate::Patch::count_changes) and `changes`.
+//!
+//! ```
+//! # use patchable::{Patch, Patchable};
+//! # use std::sync::atomic::{AtomicUsize, Ordering};
+//! static CHANGED: AtomicUsize = AtomicUsize::new(usize::MAX);
+//!
+//! fn record(changed: usize) {
+//!     CHANGED.store(changed, Ordering::Relaxed);
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(on_apply = "record", changes)]
+//! struct Window {
+//!     #[patch(group = "size")]
+//!     width: u32,
+//!     #[patch(group = "size")]
+//!     height: u32,
+//! }
+//!
+//! let partial = || WindowPatch { width: Some(800), height: None };
+//! assert_eq!(partial().count_changes(), 0);
+//!
+//! let mut window = Window { width: 640, height: 480 };
+//! let mut counted = 0;
+//! window.apply_patch_counting(partial(), &mut counted);
+//! assert_eq!((counted, CHANGED.load(Ordering::Relaxed)), (0, 0));
+//! assert_eq!(window.apply_patch_changes(partial()), []);
+//! assert_eq!(window.apply_patch_changes(WindowPatch { width: Some(800), height: Some(600) }).len(), 2);
+//! ```
+//!
+//! Fields of a group that are left out by `feature` are not counted, so the rest of the group still applies.
+//!
+//! ```
+//! # use patchable::{Patchable, TryPatchable};
+//! #[derive(Patchable)]
+//! struct Window {
+//!     #[patch(group = "size")]
+//!     width: u32,
+//!     #[patch(group = "size", feature = "extra")]
+//!     height: u32,
+//! }
+//!
+//! let mut window = Window { width: 640, height: 480 };
+//! window.apply_patch(WindowPatch { width: Some(800) });
+//! assert_eq!(window.width, 800);
+//! window.try_apply_patch(WindowPatch { width: Some(1024) }).unwrap();
+//! assert_eq!(window.width, 1024);
+//! ```
+//!
+//! `#[patch(validate_all = "path::to::fn")]` checks constraints that span fields with a `fn(&T) -> Result<(), E>`,
+//! where `E` converts into [`PatchError`](crate::PatchError), such as `PatchError` itself. `try_apply_patch`
+//! calls it after applying the patch, and if it fails, restores the value from a clone taken before and returns the
+//! error, so the struct must implement `Clone`. Hooks such as `post_apply` have already run by then. `apply_patch`
+//! does not call it.
+//!
+//! ```
+//! # use patchable::{PatchError, Patchable, TryPatchable};
+//! fn check_span(span: &Span) -> Result<(), PatchError> {
+//!     if span.start < span.end {
+//!         Ok(())
+//!     } else {
+//!         Err(PatchError::Invariant { message: "start must be before end" })
+//!     }
+//! }
+//!
+//! #[derive(Patchable, Clone)]
+//! #[patch(validate_all = "check_span")]
+//! struct Span {
+//!     start: u32,
+//!     end: u32,
+//! }
+//!
+//! let mut span = Span { start: 1, end: 5 };
+//! let result = span.try_apply_patch(SpanPatch { start: Some(6), end: None });
+//! assert_eq!(result, Err(PatchError::Invariant { message: "start must be before end" }));
+//! assert_eq!((span.start, span.end), (1, 5));
+//!
+//! span.try_apply_patch(SpanPatch { start: Some(6), end: Some(8) }).unwrap();
+//! assert_eq!((span.start, span.end), (6, 8));
+//! ```
+//!
+//! # Enums and newtypes
+//!
+//! Enums whose variants are unit variants or hold a single unnamed field can be derived as well.
+//! The patch enum has a variant of the same name for each of them, holding the patch type of the field, chosen with
+//! the same `#[patch(...)]` attributes on the variant as on struct fields. A patch is applied to the value only when
+//! both are the same variant: `apply_patch` ignores any other patch, while `try_apply_patch` returns
+//! [`PatchError::VariantMismatch`](enum@crate::PatchError).
+//!
+//! ```
+//! # use patchable::{PatchError, Patchable, TryPatchable};
+//! #[derive(Patchable)]
+//! struct Login {
+//!     user: String,
+//! }
+//!
+//! #[derive(Patchable)]
+//! enum Event {
+//!     #[patch(nested)]
+//!     Login(Login),
+//!     Message(String),
+//!     Logout,
+//! }
+//!
+//! let mut event = Event::Login(Login { user: String::from("a") });
+//! event.apply_patch(EventPatch::Login(LoginPatch { user: Some(String::from("b")) }));
+//! assert!(matches!(&event, Event::Login(login) if login.user == "b"));
+//!
+//! let result = event.try_apply_patch(EventPatch::Message(Some(String::from("hi"))));
+//! assert_eq!(result, Err(PatchError::VariantMismatch { expected: "Message", found: "Login" }));
+//! ```
+//!
+//! A struct with a single field, such as a newtype, can use `#[patch(transparent)]` to be patched with the patch
+//! type of its field instead of getting a patch struct of its own. The field takes the usual patch kinds, and
+//! `#[patch(transparent, nested)]` uses the field type's own patch, which also becomes the `HasPatch` patch of the
+//! struct.
+//!
+//! ```
+//! # use patchable::Patchable;
+//! #[derive(Patchable)]
+//! struct Inner {
+//!     name: String,
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(transparent, nested)]
+//! #[repr(transparent)]
+//! struct Id(Inner);
+//!
+//! let mut id = Id(Inner { name: String::from("a") });
+//! id.apply_patch(InnerPatch { name: Some(String::from("b")) });
+//! assert_eq!(id.0.name, "b");
+//! ```
+//!
+//! `try_apply_patch` is forwarded to the field with `nested`, and otherwise applies the patch and accepts it.
+//!
+//! ```
+//! # use patchable::{PatchError, Patchable, TryPatchable};
+//! #[derive(Patchable)]
+//! #[patch(transparent)]
+//! struct Name(String);
+//!
+//! let mut name = Name(String::from("a"));
+//! name.try_apply_patch(Some(String::from("b"))).unwrap();
+//! assert_eq!(name.0, "b");
+//!
+//! #[derive(Patchable)]
+//! struct Limit {
+//!     #[patch(validate(|max| *max > 0, "must be positive"))]
+//!     max: u32,
+//! }
+//!
+//! #[derive(Patchable)]
+//! #[patch(transparent, nested)]
+//! struct Limits(Limit);
+//!
+//! let result = Limits(Limit { max: 1 }).try_apply_patch(LimitPatch { max: Some(0) });
+//! assert_eq!(result, Err(PatchError::Validation { field: "max", message: "must be positive" }));
+//! ```
+//!
+//! # Serde and schemars
+//!
+//! With `#[patch(serde)]`, the patch struct derives serde's `Serialize` and `Deserialize`, which requires a dependency
+//! on `serde` with the `derive` feature. `#[patch(rename_all = "...")]` forwards `#[serde(rename_all = "...")]` onto the
+//! patch struct, accepting the same rules as serde, such as `camelCase`, `snake_case`, `kebab-case` and `PascalCase`.
+//! A field with `empty_if` gets `#[serde(skip_serializing_if = "...")]` with the same function, so empty patches
+//! of it are left out of the output.
+//!
+//! With `#[patch(serde)]`, `#[patch(alias = "name")]` on a field forwards `#[serde(alias = "name")]` onto the patch
+//! field, so deserialization also accepts the old key. It can be repeated, and combined with `rename`.
+//! With `#[patch(serde)]`, `#[patch(deny_unknown_fields)]` forwards `#[serde(deny_unknown_fields)]`, so keys other
+//! than the patch field names and their aliases are rejected instead of ignored.
+//!
+//! ```ignore
+//! #[derive(Patchable)]
+//! #[patch(serde, rename_all = "camelCase", deny_unknown_fields)]
+//! struct Profile {
+//!     #[patch(alias = "name")]
+//!     display_name: String,
+//! }
+//!
+//! let patch: ProfilePatch = serde_json::from_str(r#"{ "displayName": "new" }"#)?;
+//! let old: ProfilePatch = serde_json::from_str(r#"{ "name": "new" }"#)?;
+//! assert!(serde_json::from_str::<ProfilePatch>(r#"{ "displayNmae": "new" }"#).is_err());
+//! ```
+//!
+//! With `#[patch(schemars)]`, the patch struct derives `schemars::JsonSchema`, which requires a dependency on
+//! `schemars`. `#[schemars(...)]` attributes on the source fields are copied onto the patch fields. Since patch fields
+//! are `Option`s, their properties are optional in the generated schema.
+//!
+//! ```ignore
+//! #[derive(Patchable)]
+//! #[patch(serde, schemars)]
+//! struct Profile {
+//!     #[schemars(length(max = 32))]
+//!     display_name: String,
+//! }
+//!
+//! let schema = schemars::schema_for!(ProfilePatch);
+//! assert!(schema.schema.object.unwrap().required.is_empty());
+//! ```
+//!
+//! With `#[patch(serde)]`, adding `#[patch(untagged)]` to an enum forwards `#[serde(untagged)]` onto the patch enum,
+//! so a patch field can accept one of several shapes without a tag. The usual caveats of untagged enums apply:
+//! serde tries the variants in declaration order and picks the first that deserializes, so a variant whose patch
+//! accepts everything the next one does, such as one made only of `Option` fields, has to come after it, and errors
+//! do not say which variant was meant.
+//!
+//! ```ignore
+//! #[derive(Patchable)]
+//! #[patch(serde, untagged)]
+//! enum Limit {
+//!     #[patch(nested)]
+//!     Range(Range),
+//!     Fixed(u32),
+//! }
+//!
+//! let patch: LimitPatch = serde_json::from_str(r#"{ "max": 10 }"#)?;
+//! ```
@@ -1,4 +1,92 @@
+//! Patchable structs in Rust.
+//!
+//! With the default `derive` feature, a single import brings in both the trait and the derive macro.
+//!
+//! ```
+//! use patchable::Patchable;
+//!
+//! #[derive(Patchable)]
+//! struct MyStruct {
+//!     foo: String,
+//! }
+//!
+//! let mut value = MyStruct { foo: String::from("old") };
+//! value.apply_patch(MyStructPatch { foo: Some(String::from("new")) });
+//! assert_eq!(value.foo, "new");
+//! ```
+//!
+//! The options of the derive are described in the [`guide`].
+
+#[cfg(feature = "derive")]
+pub mod guide;
+
+#[cfg(feature = "derive")]
 #[doc(inline)]
 pub use patchable_macros::Patchable;
+#[cfg(feature = "derive")]
+#[doc(inline)]
+pub use patchable_macros::patchable;
 #[doc(inline)]
 pub use patchable_core::Patchable;
+#[doc(inline)]
+pub use patchable_core::TryPatchable;
+#[doc(inline)]
+pub use patchable_core::Patch;
+#[doc(inline)]
+pub use patchable_core::PatchError;
+#[doc(inline)]
+pub use patchable_core::HasPatch;
+#[doc(inline)]
+pub use patchable_core::PatchKey;
+#[doc(inline)]
+pub use patchable_core::InnerPatch;
+#[doc(inline)]
+pub use patchable_core::InitPatch;
+#[doc(inline)]
+pub use patchable_core::ListPatch;
+#[doc(inline)]
+pub use patchable_core::RangePatch;
+#[doc(inline)]
+pub use patchable_core::SplicePatch;
+#[doc(inline)]
+pub use patchable_core::PathPatch;
+#[doc(inline)]
+pub use patchable_core::TimePatch;
+#[doc(inline)]
+pub use patchable_core::Delta;
+#[doc(inline)]
+pub use patchable_core::AtomicPatchable;
+#[doc(inline)]
+pub use patchable_core::AnyPatch;
+#[doc(inline)]
+pub use patchable_core::PatchAllValues;
+#[doc(inline)]
+pub use patchable_core::PatchVisitor;
+#[doc(inline)]
+pub use patchable_core::VisitPatch;
+#[doc(inline)]
+pub use patchable_core::impl_patchable_newtype;
+
+/// Applies `patch` to `value`, the same as [`Patchable::apply_patch`], for passing to combinators.
+///
+/// ```
+/// let mut values = vec![1, 2, 3];
+/// values.iter_mut().zip([Some(4), None, Some(6)]).for_each(|(value, patch)| patchable::apply(value, patch));
+/// assert_eq!(values, [4, 2, 6]);
+/// ```
+#[inline]
+pub fn apply<T, P>(value: &mut T, patch: P) where T: Patchable<P> + ?Sized {
+    value.apply_patch(patch);
+}
+
+/// Applies `patch` to `value` and returns it.
+///
+/// ```
+/// let values: Vec<i32> = vec![1, 2].into_iter().map(|value| patchable::applied(value, Some(0))).collect();
+/// assert_eq!(values, [0, 0]);
+/// ```
+#[inline]
+pub fn applied<T, P>(mut value: T, patch: P) -> T where T: Patchable<P> {
+    value.apply_patch(patch);
+    value
+}